@@ -2,4 +2,12 @@ pub static INDEX_TEMPLATE: &str = include_str!("../templates/index.html");
 
 pub static NOTE_TEMPLATE: &str = include_str!("../templates/note.html");
 
+pub static TASKS_TEMPLATE: &str = include_str!("../templates/tasks.html");
+
+pub static GLOSSARY_TEMPLATE: &str = include_str!("../templates/glossary.html");
+
 pub static MAIN_CSS: &str = include_str!("../templates/main.css");
+
+pub static ATOM_TEMPLATE: &str = include_str!("../templates/feed.xml");
+
+pub static RSS_TEMPLATE: &str = include_str!("../templates/rss.xml");
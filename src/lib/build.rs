@@ -1,6 +1,8 @@
+use super::config::{IndexConfig, SortKey};
 use super::meta::Metadatum;
 use super::note::Note;
-use crate::assets::{INDEX_TEMPLATE, MAIN_CSS};
+use crate::assets::{GLOSSARY_TEMPLATE, INDEX_TEMPLATE, MAIN_CSS, TASKS_TEMPLATE};
+use chrono::{DateTime, Datelike, Utc};
 use handlebars::Handlebars;
 use serde_derive::Serialize;
 use serde_json::json;
@@ -8,14 +10,95 @@ use std::error::Error;
 use std::fs;
 use std::path::Path;
 
-#[derive(Serialize)]
-pub struct Link {
+pub struct IndexEntry {
     pub href: String,
     pub title: String,
+    pub created: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+    pub word_count: usize,
+    pub tags: Vec<String>,
+    pub pinned: bool,
+}
+
+/// An open task, and where it is.
+pub struct TaskEntry {
+    pub filename: String,
+    pub line: usize,
+    pub note_title: String,
+    pub note_href: String,
+    /// The task's anchor within the note's page
+    pub href: String,
+    pub text: String,
+}
+
+/// A term defined in a note.
+#[derive(Serialize)]
+pub struct GlossaryEntry {
+    pub term: String,
+    pub definitions: Vec<String>,
+    pub note_title: String,
+    /// The term's anchor within the note's page
+    pub href: String,
+}
+
+#[derive(Serialize)]
+struct TaskGroup<'a> {
+    title: &'a str,
+    href: &'a str,
+    tasks: Vec<Task<'a>>,
+}
+
+#[derive(Serialize)]
+struct Task<'a> {
+    href: &'a str,
+    line: usize,
+    text: &'a str,
+}
+
+#[derive(Serialize)]
+struct Year<'a> {
+    year: i32,
+    months: Vec<Month<'a>>,
+}
+
+#[derive(Serialize)]
+struct Month<'a> {
+    month: String,
+    entries: Vec<Entry<'a>>,
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    href: &'a str,
+    title: &'a str,
+    created: String,
+    // Only present if the note was updated on a later day than it was created
+    updated: Option<String>,
+    word_count: usize,
+    tags: &'a [String],
+}
+
+impl<'a> Entry<'a> {
+    fn new(entry: &'a IndexEntry, date_format: &str) -> Self {
+        let updated = if entry.updated.naive_utc().date() > entry.created.naive_utc().date() {
+            Some(entry.updated.format("%b %e %Y").to_string())
+        } else {
+            None
+        };
+
+        Entry {
+            href: &entry.href,
+            title: &entry.title,
+            created: entry.created.format(date_format).to_string(),
+            updated,
+            word_count: entry.word_count,
+            tags: &entry.tags,
+        }
+    }
 }
 
 pub fn prep_build_dir(build_dir: &Path) -> Result<(), Box<dyn Error>> {
-    if let Ok(_) = fs::read_dir(build_dir) {
+    if fs::read_dir(build_dir).is_ok() {
         fs::remove_dir_all(build_dir)?;
     }
 
@@ -44,16 +127,180 @@ pub fn write_metadata(metadata: Vec<Metadatum>, meta_path: &Path) -> Result<(),
     Ok(())
 }
 
-pub fn write_index(links: Vec<Link>, build_dir: &Path) -> Result<(), Box<dyn Error>> {
-    let index = generate_index(links)?;
+pub fn write_index(
+    entries: Vec<IndexEntry>,
+    config: &IndexConfig,
+    sort_key: SortKey,
+    build_dir: &Path,
+) -> Result<(), Box<dyn Error>> {
+    // Pinned notes are sorted to the front, and are only listed on the first page
+    let pinned_count = entries.iter().take_while(|e| e.pinned).count();
+    let (pinned, entries) = entries.split_at(pinned_count);
+
+    let page_size = match config.page_size {
+        Some(size) if size > 0 => size,
+        _ => entries.len().max(1),
+    };
+    let pages = entries.chunks(page_size).collect::<Vec<&[IndexEntry]>>();
+    let page_count = pages.len().max(1);
+
+    for number in 1..=page_count {
+        let page = pages.get(number - 1).copied().unwrap_or(&[]);
+        let pinned = if number == 1 { pinned } else { &[] };
+        let prev = if number > 1 {
+            Some(index_page_path(number - 1))
+        } else {
+            None
+        };
+        let next = if number < page_count {
+            Some(index_page_path(number + 1))
+        } else {
+            None
+        };
 
-    fs::write(build_dir.join(Path::new("index.html")), index)?;
+        let index = generate_index(pinned, page, sort_key, number, page_count, prev, next)?;
+        fs::write(build_dir.join(Path::new(&index_page_path(number))), index)?;
+    }
 
     Ok(())
 }
 
-fn generate_index(links: Vec<Link>) -> Result<String, Box<dyn Error>> {
-    let html = Handlebars::new().render_template(INDEX_TEMPLATE, &json!({ "links": links }))?;
+fn index_page_path(number: usize) -> String {
+    if number == 1 {
+        String::from("index.html")
+    } else {
+        format!("index-{}.html", number)
+    }
+}
+
+fn generate_index(
+    pinned: &[IndexEntry],
+    entries: &[IndexEntry],
+    sort_key: SortKey,
+    number: usize,
+    page_count: usize,
+    prev: Option<String>,
+    next: Option<String>,
+) -> Result<String, Box<dyn Error>> {
+    let pinned = pinned
+        .iter()
+        .map(|entry| Entry::new(entry, "%b %e %Y"))
+        .collect::<Vec<Entry>>();
+
+    // Notes sorted by date are grouped by year and month; otherwise they're
+    // listed as-is
+    let (years, entries) = match sort_key {
+        SortKey::Created => (group_entries(entries, |e| e.created), vec![]),
+        SortKey::Updated => (group_entries(entries, |e| e.updated), vec![]),
+        SortKey::Title | SortKey::Manual => (
+            vec![],
+            entries
+                .iter()
+                .map(|entry| Entry::new(entry, "%b %e %Y"))
+                .collect::<Vec<Entry>>(),
+        ),
+    };
+
+    let html = Handlebars::new().render_template(
+        INDEX_TEMPLATE,
+        &json!({
+            "pinned": pinned,
+            "years": years,
+            "entries": entries,
+            "paginated": page_count > 1,
+            "page": number,
+            "page_count": page_count,
+            "prev": prev,
+            "next": next
+        }),
+    )?;
 
     Ok(html)
 }
+
+/// Groups consecutive entries whose `date` falls in the same year and month.
+fn group_entries<F>(entries: &[IndexEntry], date: F) -> Vec<Year<'_>>
+where
+    F: Fn(&IndexEntry) -> DateTime<Utc>,
+{
+    let mut years: Vec<Year> = vec![];
+
+    for entry in entries {
+        let year = date(entry).year();
+        let month = date(entry).format("%B").to_string();
+
+        if years.last().map(|y| y.year) != Some(year) {
+            years.push(Year {
+                year,
+                months: vec![],
+            });
+        }
+        let months = &mut years.last_mut().unwrap().months;
+
+        if months.last().map(|m| &m.month) != Some(&month) {
+            months.push(Month {
+                month,
+                entries: vec![],
+            });
+        }
+        months
+            .last_mut()
+            .unwrap()
+            .entries
+            .push(Entry::new(entry, "%b %e"));
+    }
+
+    years
+}
+
+/// Writes a page listing every open task, grouped by note.
+pub fn write_tasks(entries: &[TaskEntry], build_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let mut groups: Vec<TaskGroup> = vec![];
+
+    for entry in entries {
+        if groups.last().map(|g| g.href) != Some(entry.note_href.as_str()) {
+            groups.push(TaskGroup {
+                title: &entry.note_title,
+                href: &entry.note_href,
+                tasks: vec![],
+            });
+        }
+        groups.last_mut().unwrap().tasks.push(Task {
+            href: &entry.href,
+            line: entry.line,
+            text: &entry.text,
+        });
+    }
+
+    let html = Handlebars::new().render_template(
+        TASKS_TEMPLATE,
+        &json!({
+            "groups": groups,
+            "count": entries.len()
+        }),
+    )?;
+    fs::write(build_dir.join(Path::new("tasks.html")), html)?;
+
+    Ok(())
+}
+
+/// Prints each open task as "file:line: task".
+pub fn print_tasks(entries: &[TaskEntry]) {
+    for entry in entries {
+        println!("{}:{}: {}", entry.filename, entry.line, entry.text);
+    }
+}
+
+/// Writes a page listing every term defined in every note, alphabetically.
+pub fn write_glossary(
+    mut entries: Vec<GlossaryEntry>,
+    build_dir: &Path,
+) -> Result<(), Box<dyn Error>> {
+    entries.sort_by_cached_key(|e| (e.term.to_lowercase(), e.note_title.to_lowercase()));
+
+    let html =
+        Handlebars::new().render_template(GLOSSARY_TEMPLATE, &json!({ "entries": entries }))?;
+    fs::write(build_dir.join(Path::new("glossary.html")), html)?;
+
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use super::config::Config;
+use super::error::JustTextError;
+
+pub enum Command {
+    /// Build the site (the default)
+    Build,
+    /// Print every open task
+    Tasks,
+}
+
+/// Overrides settings in `config` with those given on the command line, and
+/// returns the command, which (if given) must come before any settings.
+pub fn apply_args<I>(config: &mut Config, args: I) -> Result<Command, JustTextError<'static>>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter().peekable();
+
+    let command = match args.peek().map(String::as_str) {
+        Some("tasks") => {
+            args.next();
+            Command::Tasks
+        }
+        _ => Command::Build,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--sort" => {
+                config.sort.key = expect_value(&arg, args.next())?.parse()?;
+            }
+            "--direction" => {
+                config.sort.direction = expect_value(&arg, args.next())?.parse()?;
+            }
+            "--pin" => {
+                config.sort.pinned.push(expect_value(&arg, args.next())?);
+            }
+            "--strict" => {
+                config.strict = true;
+            }
+            _ => {
+                return Err(JustTextError::new(format!("Unknown argument \"{}\"", arg)));
+            }
+        }
+    }
+
+    Ok(command)
+}
+
+fn expect_value(arg: &str, value: Option<String>) -> Result<String, JustTextError<'static>> {
+    value.ok_or_else(|| JustTextError::new(format!("Expected a value after \"{}\"", arg)))
+}
@@ -0,0 +1,122 @@
+use super::error::JustTextError;
+use serde_derive::Deserialize;
+use std::str::FromStr;
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Report malformed markup as an error, rather than rendering it as text
+    pub strict: bool,
+    /// Render footnotes in the margin beside the text, rather than at the end
+    /// of each note
+    pub sidenotes: bool,
+    /// Render straight quotes, "--", "---", and "..." as curly quotes, dashes,
+    /// and ellipses
+    pub typography: bool,
+    /// Replace shortcodes like ":tada:" with emoji
+    pub emoji: bool,
+    pub sort: SortConfig,
+    pub index: IndexConfig,
+    /// Feeds are only written if this is set
+    pub feed: Option<FeedConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            strict: false,
+            sidenotes: false,
+            typography: false,
+            emoji: true,
+            sort: SortConfig::default(),
+            index: IndexConfig::default(),
+            feed: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct SortConfig {
+    pub key: SortKey,
+    pub direction: Direction,
+    /// Titles of notes to list before all others
+    pub pinned: Vec<String>,
+    /// Titles of notes in the order used by `SortKey::Manual`
+    pub order: Vec<String>,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    #[default]
+    Created,
+    Updated,
+    Title,
+    Manual,
+}
+
+impl FromStr for SortKey {
+    type Err = JustTextError<'static>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "created" => Ok(SortKey::Created),
+            "updated" => Ok(SortKey::Updated),
+            "title" => Ok(SortKey::Title),
+            "manual" => Ok(SortKey::Manual),
+            _ => Err(JustTextError::new(format!("Unknown sort key \"{}\"", s))),
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl FromStr for Direction {
+    type Err = JustTextError<'static>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "asc" => Ok(Direction::Asc),
+            "desc" => Ok(Direction::Desc),
+            _ => Err(JustTextError::new(format!(
+                "Unknown sort direction \"{}\"",
+                s
+            ))),
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct IndexConfig {
+    pub page_size: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct FeedConfig {
+    pub title: String,
+    pub author: Option<String>,
+    pub base_url: String,
+    pub limit: usize,
+    pub rss: bool,
+}
+
+impl Default for FeedConfig {
+    fn default() -> Self {
+        FeedConfig {
+            title: String::from("Notes"),
+            author: None,
+            base_url: String::new(),
+            limit: 20,
+            rss: false,
+        }
+    }
+}
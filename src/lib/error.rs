@@ -5,6 +5,9 @@ use std::fmt;
 #[derive(Debug, PartialEq)]
 pub struct JustTextError<'a> {
     message: Cow<'a, str>,
+    /// Where in a note's body the error occurred, for errors found while
+    /// parsing a note
+    pub pos: Option<usize>,
 }
 
 impl<'a> JustTextError<'a> {
@@ -14,6 +17,17 @@ impl<'a> JustTextError<'a> {
     {
         JustTextError {
             message: message.into(),
+            pos: None,
+        }
+    }
+
+    pub fn at<S>(message: S, pos: usize) -> Self
+    where
+        S: Into<Cow<'a, str>>,
+    {
+        JustTextError {
+            message: message.into(),
+            pos: Some(pos),
         }
     }
 }
@@ -0,0 +1,216 @@
+use super::config::FeedConfig;
+use super::note::Note;
+use crate::assets::{ATOM_TEMPLATE, RSS_TEMPLATE};
+use chrono::{DateTime, Utc};
+use handlebars::Handlebars;
+use serde_derive::Serialize;
+use serde_json::json;
+use std::cmp::Reverse;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+#[derive(Serialize)]
+struct Entry {
+    title: String,
+    permalink: String,
+    created: String,
+    updated: String,
+    content: String,
+}
+
+enum Format {
+    Atom,
+    Rss,
+}
+
+impl Format {
+    fn template(&self) -> &'static str {
+        match self {
+            Format::Atom => ATOM_TEMPLATE,
+            Format::Rss => RSS_TEMPLATE,
+        }
+    }
+
+    fn filename(&self) -> &'static str {
+        match self {
+            Format::Atom => "feed.xml",
+            Format::Rss => "rss.xml",
+        }
+    }
+
+    fn format_date(&self, date: &DateTime<Utc>) -> String {
+        match self {
+            Format::Atom => date.to_rfc3339(),
+            Format::Rss => date.to_rfc2822(),
+        }
+    }
+}
+
+/// The author of the feed when `author` isn't set, since Atom requires one
+const DEFAULT_AUTHOR: &str = "Anonymous";
+
+/// Writes an Atom feed (and an RSS feed, if configured) containing the `limit`
+/// most recently created notes, in the same order as the index. Feeds identify
+/// their entries by absolute URLs, so they're only written once `base_url` is
+/// set.
+pub fn write_feeds(
+    notes: &[Note],
+    config: &FeedConfig,
+    build_dir: &Path,
+) -> Result<(), Box<dyn Error>> {
+    if config.base_url.trim().is_empty() {
+        eprintln!(
+            "warning: Feeds aren't written until \"base_url\" is set in the \"feed\" settings"
+        );
+        return Ok(());
+    }
+
+    write_feed(Format::Atom, notes, config, build_dir)?;
+    if config.rss {
+        write_feed(Format::Rss, notes, config, build_dir)?;
+    }
+
+    Ok(())
+}
+
+fn write_feed(
+    format: Format,
+    notes: &[Note],
+    config: &FeedConfig,
+    build_dir: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let feed = generate_feed(&format, notes, config)?;
+
+    fs::write(build_dir.join(Path::new(format.filename())), feed)?;
+
+    Ok(())
+}
+
+fn generate_feed(
+    format: &Format,
+    notes: &[Note],
+    config: &FeedConfig,
+) -> Result<String, Box<dyn Error>> {
+    let base_url = config.base_url.trim_end_matches('/');
+    let mut newest = (0..notes.len()).collect::<Vec<usize>>();
+    newest.sort_by_key(|&i| Reverse(notes[i].created));
+    newest.truncate(config.limit);
+    newest.sort_unstable();
+    let notes = newest
+        .into_iter()
+        .map(|i| &notes[i])
+        .collect::<Vec<&Note>>();
+
+    let mut entries = vec![];
+    for note in &notes {
+        entries.push(Entry {
+            title: note.generate_title(),
+            permalink: note.generate_permalink(base_url),
+            created: format.format_date(&note.created),
+            updated: format.format_date(&note.updated),
+            content: note.render(),
+        });
+    }
+    let updated = notes
+        .iter()
+        .map(|note| note.updated)
+        .max()
+        .unwrap_or_else(Utc::now);
+
+    let mut handlebars = Handlebars::new();
+    handlebars.register_escape_fn(escape_xml);
+    let xml = handlebars.render_template(
+        format.template(),
+        &json!({
+            "title": config.title,
+            "author": config.author.as_deref().unwrap_or(DEFAULT_AUTHOR),
+            "base_url": base_url,
+            "updated": format.format_date(&updated),
+            "entries": entries
+        }),
+    )?;
+
+    Ok(xml)
+}
+
+fn escape_xml(input: &str) -> String {
+    let mut result = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&apos;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::note::test_note;
+
+    #[test]
+    fn generate_feed1() {
+        let config = FeedConfig {
+            base_url: String::from("https://example.com/notes/"),
+            ..FeedConfig::default()
+        };
+        let xml = generate_feed(&Format::Atom, &[], &config).unwrap();
+
+        assert!(xml.contains("<id>https://example.com/notes/</id>"));
+        assert!(xml.contains("<name>Anonymous</name>"));
+    }
+
+    #[test]
+    fn generate_feed2() {
+        // In title order, as with "--sort title"
+        let notes = vec![
+            test_note("notes/A.txt", "", 1),
+            test_note("notes/B.txt", "", 3),
+            test_note("notes/C.txt", "", 2),
+        ];
+        let config = FeedConfig {
+            base_url: String::from("https://example.com"),
+            limit: 2,
+            ..FeedConfig::default()
+        };
+        let xml = generate_feed(&Format::Atom, &notes, &config).unwrap();
+
+        assert!(!xml.contains("<title>A</title>"));
+        let b = xml.find("<title>B</title>").unwrap();
+        let c = xml.find("<title>C</title>").unwrap();
+        assert!(b < c);
+    }
+
+    #[test]
+    fn generate_feed3() {
+        let notes = vec![test_note("notes/Über uns.txt", "See [B](./B.html).", 1)];
+        let config = FeedConfig {
+            base_url: String::from("https://example.com"),
+            rss: true,
+            ..FeedConfig::default()
+        };
+        let permalink = "https://example.com/%C3%9Cber%20uns.html";
+
+        // Links in the content are relative to the note's page
+        let xml = generate_feed(&Format::Atom, &notes, &config).unwrap();
+        assert!(xml.contains(&format!("<entry xml:base=\"{}\">", permalink)));
+        assert!(xml.contains(&format!("<id>{}</id>", permalink)));
+
+        let xml = generate_feed(&Format::Rss, &notes, &config).unwrap();
+        assert!(xml.contains(&format!("<item xml:base=\"{}\">", permalink)));
+    }
+
+    #[test]
+    fn escape_xml1() {
+        assert_eq!(
+            escape_xml("<p>Tom & \"Jerry's\"</p>"),
+            "&lt;p&gt;Tom &amp; &quot;Jerry&apos;s&quot;&lt;/p&gt;"
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use super::error::JustTextError;
+use super::note::Note;
+
+/// Checks that each link to a heading (or other anchor) in another note, like
+/// "Other_Note.html#section", leads somewhere. Linking to a note that doesn't
+/// exist, or to an id that the note doesn't have, is an error.
+pub fn check_links(notes: &[Note]) -> Result<(), JustTextError<'static>> {
+    for note in notes {
+        for (href, pos) in note.links() {
+            let (page, id) = match note_anchor(&href) {
+                Some(anchor) => anchor,
+                None => continue,
+            };
+
+            let target = match notes.iter().find(|n| n.is_at(&page)) {
+                Some(target) => target,
+                None if is_site_page(&page) => continue,
+                None => {
+                    return Err(JustTextError::new(format!(
+                        "{}: Can't link to \"{}\", since there's no such note",
+                        note.locate(pos),
+                        href
+                    )));
+                }
+            };
+
+            if !target.anchors().contains(&id) {
+                return Err(JustTextError::new(format!(
+                    "{}: Can't link to \"{}\", since \"{}\" has no \"#{}\" heading",
+                    note.locate(pos),
+                    href,
+                    target.generate_title(),
+                    id
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Splits a link to somewhere within a page of the build directory, like
+/// "./Note.html#id", into the page and the id (both percent-decoded).
+fn note_anchor(href: &str) -> Option<(String, String)> {
+    let (page, id) = href.split_once('#')?;
+    let page = page.strip_prefix("./").unwrap_or(page);
+    if !page.ends_with(".html") || page.contains(['/', ':']) {
+        return None;
+    }
+
+    Some((percent_decode(page), percent_decode(id)))
+}
+
+/// Whether `page` is one of the pages that are built besides the notes'.
+fn is_site_page(page: &str) -> bool {
+    page.starts_with("index") || page == "tasks.html" || page == "glossary.html"
+}
+
+fn percent_decode(s: &str) -> String {
+    let mut bytes = vec![];
+    let mut rest = s.as_bytes();
+    while let Some((&byte, after)) = rest.split_first() {
+        let hex = after.get(..2).and_then(|hex| std::str::from_utf8(hex).ok());
+        match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+            Some(decoded) if byte == b'%' => {
+                bytes.push(decoded);
+                rest = &after[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = after;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::note::test_note;
+
+    fn note(filename: &str, content: &str) -> Note {
+        test_note(filename, content, 1)
+    }
+
+    #[test]
+    fn check_links1() {
+        let notes = vec![
+            note(
+                "notes/A.txt",
+                "See [setup](B.html#setup), [Über](./B.html#%C3%BCber-uns), \
+                 [the index](index.html#tags), and [elsewhere](https://example.com/x.html#y).",
+            ),
+            note("notes/B.txt", "# Setup\n\n# Über uns"),
+        ];
+        assert_eq!(check_links(&notes), Ok(()));
+
+        let notes = vec![
+            note("notes/A.txt", "Text\n\n[Install](B.html#install)"),
+            note("notes/B.txt", "# Setup"),
+        ];
+        assert_eq!(
+            check_links(&notes),
+            Err(JustTextError::new(
+                "notes/A.txt:3:11: Can't link to \"B.html#install\", since \"B\" has no \"#install\" heading"
+            ))
+        );
+
+        let notes = vec![note("notes/A.txt", "[Setup](C.html#setup)")];
+        assert_eq!(
+            check_links(&notes),
+            Err(JustTextError::new(
+                "notes/A.txt:1:9: Can't link to \"C.html#setup\", since there's no such note"
+            ))
+        );
+    }
+
+    #[test]
+    fn percent_decode1() {
+        assert_eq!(percent_decode("%C3%BCber%20uns"), "über uns");
+        assert_eq!(percent_decode("100%"), "100%");
+    }
+}
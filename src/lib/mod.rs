@@ -1,38 +1,76 @@
 mod build;
+mod cli;
+mod config;
 mod error;
+mod feed;
+mod links;
 mod meta;
 mod note;
 mod read;
+mod sort;
+mod transclude;
 
-use self::build::Link;
+use self::build::{GlossaryEntry, IndexEntry, TaskEntry};
+use self::cli::Command;
 use self::meta::Metadatum;
+use std::env;
 use std::error::Error;
 use std::path::Path;
 
 pub fn run() -> Result<(), Box<dyn Error>> {
+    let config_path = Path::new("just_text.json");
     let meta_path = Path::new(".notes");
     let build_dir = Path::new("build");
     let notes_dir = Path::new("notes");
 
-    let metadata = read::read_metadata(&meta_path).unwrap_or(vec![]);
-    let mut notes = read::read_notes(&notes_dir)?;
+    let mut config = read::read_config(config_path)?;
+    let command = cli::apply_args(&mut config, env::args().skip(1))?;
 
-    notes.iter_mut().for_each(|note| note.reconcile(&metadata));
-    notes.sort_by(|a, b| a.created.cmp(&b.created));
+    let metadata = read::read_metadata(meta_path).unwrap_or(vec![]);
+    let mut notes = read::read_notes(notes_dir)?;
+
+    for note in notes.iter_mut() {
+        note.reconcile(&metadata);
+        note.parse(&config)?;
+        for warning in note.warnings() {
+            eprintln!("warning: {}", warning);
+        }
+    }
+    transclude::transclude(&mut notes)?;
+    links::check_links(&notes)?;
+    sort::sort_notes(&mut notes, &config.sort);
+
+    let task_entries = notes
+        .iter()
+        .flat_map(|note| note.generate_task_entries())
+        .collect::<Vec<TaskEntry>>();
+    if let Command::Tasks = command {
+        build::print_tasks(&task_entries);
+        return Ok(());
+    }
 
     let metadata = notes
         .iter()
         .map(|note| note.to_metadatum())
         .collect::<Vec<Metadatum>>();
-    let links = notes
+    let index_entries = notes
+        .iter()
+        .map(|note| note.generate_index_entry())
+        .collect::<Vec<IndexEntry>>();
+    let glossary_entries = notes
         .iter()
-        .map(|note| note.generate_link())
-        .collect::<Vec<Link>>();
+        .flat_map(|note| note.generate_glossary_entries())
+        .collect::<Vec<GlossaryEntry>>();
 
-    build::prep_build_dir(&build_dir)?;
-    build::write_index(links, &build_dir)?;
-    build::write_notes(notes, &build_dir)?;
-    build::write_metadata(metadata, &meta_path)?;
+    build::prep_build_dir(build_dir)?;
+    build::write_index(index_entries, &config.index, config.sort.key, build_dir)?;
+    build::write_tasks(&task_entries, build_dir)?;
+    build::write_glossary(glossary_entries, build_dir)?;
+    if let Some(feed_config) = &config.feed {
+        feed::write_feeds(&notes, feed_config, build_dir)?;
+    }
+    build::write_notes(notes, build_dir)?;
+    build::write_metadata(metadata, meta_path)?;
 
     Ok(())
 }
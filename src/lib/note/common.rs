@@ -1 +1,73 @@
 pub type Span = (usize, usize);
+
+pub fn escape_html(input: &str) -> String {
+    let mut result = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Like `unescape`, but also replaces straight quotes with curly ones, "--"
+/// and "---" with en and em dashes, and "..." with an ellipsis. Escaped chars
+/// are left as they are. `before` is the char preceding `input`, which decides
+/// whether a quote at its start opens or closes.
+pub fn smarten(input: &str, before: Option<char>) -> String {
+    let mut result = String::with_capacity(input.len());
+    let mut prev = before;
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        let next = match c {
+            '\\' if chars.peek().is_some_and(|n| n.is_ascii_punctuation()) => chars.next().unwrap(),
+            '"' | '\'' => {
+                let opens =
+                    prev.is_none_or(|p| p.is_whitespace() || "([{<\u{2013}\u{2014}".contains(p));
+                match (c, opens) {
+                    ('"', true) => '\u{201c}',
+                    ('"', false) => '\u{201d}',
+                    ('\'', true) => '\u{2018}',
+                    _ => '\u{2019}',
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                chars.next();
+                if chars.peek() == Some(&'-') {
+                    chars.next();
+                    '\u{2014}'
+                } else {
+                    '\u{2013}'
+                }
+            }
+            '.' if chars.clone().take(2).eq("..".chars()) => {
+                chars.nth(1);
+                '\u{2026}'
+            }
+            _ => c,
+        };
+        result.push(next);
+        prev = Some(next);
+    }
+    result
+}
+
+/// Replaces each backslash escape (e.g. "\[") with the char it escapes.
+pub fn unescape(input: &str) -> String {
+    let mut result = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(next) if c == '\\' && next.is_ascii_punctuation() => {
+                result.push(*next);
+                chars.next();
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
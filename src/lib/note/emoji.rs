@@ -0,0 +1,210 @@
+/// Emoji (and a few symbols) by their shortcode, sorted by shortcode so that
+/// they can be binary searched
+const EMOJI: &[(&str, &str)] = &[
+    ("+1", "👍"),
+    ("-1", "👎"),
+    ("100", "💯"),
+    ("airplane", "✈️"),
+    ("alarm_clock", "⏰"),
+    ("angry", "😠"),
+    ("apple", "🍎"),
+    ("arrow_down", "⬇️"),
+    ("arrow_left", "⬅️"),
+    ("arrow_right", "➡️"),
+    ("arrow_up", "⬆️"),
+    ("art", "🎨"),
+    ("baby", "👶"),
+    ("balloon", "🎈"),
+    ("bang", "❗"),
+    ("beer", "🍺"),
+    ("bell", "🔔"),
+    ("bike", "🚲"),
+    ("birthday", "🎂"),
+    ("blush", "😊"),
+    ("book", "📖"),
+    ("books", "📚"),
+    ("boom", "💥"),
+    ("bug", "🐛"),
+    ("bulb", "💡"),
+    ("cake", "🍰"),
+    ("calendar", "📅"),
+    ("camera", "📷"),
+    ("car", "🚗"),
+    ("cat", "🐱"),
+    ("chart", "📈"),
+    ("check", "✔️"),
+    ("clap", "👏"),
+    ("clipboard", "📋"),
+    ("cloud", "☁️"),
+    ("coffee", "☕"),
+    ("computer", "💻"),
+    ("confused", "😕"),
+    ("construction", "🚧"),
+    ("cookie", "🍪"),
+    ("copyright", "©️"),
+    ("crossed_fingers", "🤞"),
+    ("cry", "😢"),
+    ("dart", "🎯"),
+    ("dog", "🐶"),
+    ("email", "📧"),
+    ("eyes", "👀"),
+    ("fire", "🔥"),
+    ("flag", "🚩"),
+    ("flower", "🌸"),
+    ("frowning", "😦"),
+    ("gear", "⚙️"),
+    ("gift", "🎁"),
+    ("globe", "🌍"),
+    ("grin", "😁"),
+    ("grinning", "😀"),
+    ("hammer", "🔨"),
+    ("heart", "❤️"),
+    ("heavy_check_mark", "✔️"),
+    ("hourglass", "⌛"),
+    ("house", "🏠"),
+    ("hugs", "🤗"),
+    ("info", "ℹ️"),
+    ("joy", "😂"),
+    ("key", "🔑"),
+    ("laughing", "😆"),
+    ("leaves", "🍃"),
+    ("link", "🔗"),
+    ("lock", "🔒"),
+    ("mag", "🔍"),
+    ("memo", "📝"),
+    ("moon", "🌙"),
+    ("muscle", "💪"),
+    ("music", "🎵"),
+    ("no_entry", "⛔"),
+    ("ok", "🆗"),
+    ("ok_hand", "👌"),
+    ("paperclip", "📎"),
+    ("party", "🎉"),
+    ("pencil", "✏️"),
+    ("phone", "📱"),
+    ("pin", "📌"),
+    ("pizza", "🍕"),
+    ("point_right", "👉"),
+    ("pray", "🙏"),
+    ("question", "❓"),
+    ("rainbow", "🌈"),
+    ("recycle", "♻️"),
+    ("registered", "®️"),
+    ("rocket", "🚀"),
+    ("rose", "🌹"),
+    ("sad", "😞"),
+    ("scissors", "✂️"),
+    ("see_no_evil", "🙈"),
+    ("seedling", "🌱"),
+    ("smile", "😄"),
+    ("smiley", "😃"),
+    ("smirk", "😏"),
+    ("snowflake", "❄️"),
+    ("sob", "😭"),
+    ("sparkles", "✨"),
+    ("star", "⭐"),
+    ("stop_sign", "🛑"),
+    ("sunglasses", "😎"),
+    ("sunny", "☀️"),
+    ("sweat_smile", "😅"),
+    ("tada", "🎉"),
+    ("thinking", "🤔"),
+    ("thumbsdown", "👎"),
+    ("thumbsup", "👍"),
+    ("tm", "™️"),
+    ("tree", "🌳"),
+    ("trophy", "🏆"),
+    ("umbrella", "☂️"),
+    ("unlock", "🔓"),
+    ("warning", "⚠️"),
+    ("wave", "👋"),
+    ("white_check_mark", "✅"),
+    ("wink", "😉"),
+    ("wrench", "🔧"),
+    ("x", "❌"),
+    ("yum", "😋"),
+    ("zap", "⚡"),
+    ("zzz", "💤"),
+];
+
+/// The emoji that `name` (a shortcode without its colons) stands for.
+pub fn lookup(name: &str) -> Option<&'static str> {
+    EMOJI
+        .binary_search_by_key(&name, |(shortcode, _)| shortcode)
+        .ok()
+        .map(|i| EMOJI[i].1)
+}
+
+/// Finds the shortcodes in `input`, like ":tada:", returning each one's span
+/// within `input`. `before` is the char preceding `input`. A shortcode can't
+/// be escaped or touch a word or another colon, so that times like "10:30:00"
+/// and paths like "std::fmt::Display" aren't mistaken for one.
+pub fn shortcodes(input: &str, before: Option<char>) -> Vec<(usize, usize)> {
+    let is_name_char = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || "_+-".contains(c);
+    let touches = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == ':');
+
+    let mut result = vec![];
+    let mut i = 0;
+    while let Some(start) = input[i..].find(':').map(|n| n + i) {
+        let prev = input[..start].chars().next_back().or(before);
+        let len = input[start + 1..]
+            .find(|c| !is_name_char(c))
+            .unwrap_or(input.len() - start - 1);
+        let end = start + 1 + len;
+
+        if len > 0
+            && input[end..].starts_with(':')
+            && !touches(prev)
+            && prev != Some('\\')
+            && !touches(input[end + 1..].chars().next())
+        {
+            result.push((start, end + 1));
+            i = end + 1;
+        } else {
+            i = start + 1;
+        }
+    }
+    result
+}
+
+/// Replaces each known shortcode in `input` with its emoji. Unknown ones are
+/// left as they are.
+pub fn emojify(input: &str, before: Option<char>) -> String {
+    let mut result = String::with_capacity(input.len());
+    let mut last = 0;
+    for (start, end) in shortcodes(input, before) {
+        if let Some(emoji) = lookup(&input[start + 1..end - 1]) {
+            result.push_str(&input[last..start]);
+            result.push_str(emoji);
+            last = end;
+        }
+    }
+    result.push_str(&input[last..]);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emoji_sorted1() {
+        assert!(EMOJI.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn shortcodes1() {
+        assert_eq!(shortcodes(":tada: and :+1:", None), vec![(0, 6), (11, 15)]);
+        assert_eq!(shortcodes("at 10:30:00", None), vec![]);
+        assert_eq!(shortcodes("std::fmt::Display", None), vec![]);
+        assert_eq!(shortcodes("\\:tada: or a:b:c", None), vec![]);
+        assert_eq!(shortcodes(":tada:", Some('x')), vec![]);
+        assert_eq!(shortcodes("::tada:", None), vec![]);
+    }
+
+    #[test]
+    fn emojify1() {
+        assert_eq!(emojify("Done :tada: :nope:", None), "Done 🎉 :nope:");
+        assert_eq!(emojify(":fire::fire:", None), ":fire::fire:");
+    }
+}
@@ -0,0 +1,164 @@
+use crate::lib::error::JustTextError;
+
+/// Per-note settings, written as `% key: value` lines at the very top of a
+/// note:
+///
+/// ```text
+/// % tags: rust, parsing
+///
+/// The note itself starts here...
+/// ```
+#[derive(Debug, Default, PartialEq)]
+pub struct Header {
+    pub tags: Vec<String>,
+    /// Render each line break as-is, rather than joining lines into paragraphs
+    pub preserve_lines: bool,
+    /// Overrides the project's `sidenotes` setting for this note
+    pub sidenotes: Option<bool>,
+    /// Set to `false` to leave bare URLs and email addresses unlinked
+    pub autolink: Option<bool>,
+    /// Overrides the project's `typography` setting for this note
+    pub typography: Option<bool>,
+    /// Overrides the project's `emoji` setting for this note
+    pub emoji: Option<bool>,
+}
+
+impl Header {
+    /// Reads the header from the top of `content`, returning it along with the
+    /// offset at which the body of the note begins. The header ends at the
+    /// first line that isn't a known setting, so a first line like "% done:
+    /// 40%" is part of the body.
+    pub fn parse(content: &str) -> Result<(Header, usize), JustTextError<'static>> {
+        let mut header = Header::default();
+        let mut body_start = 0;
+
+        for line in content.split_inclusive('\n') {
+            let setting = match parse_setting(line) {
+                Some(setting) => setting,
+                None => break,
+            };
+
+            match setting {
+                ("tags", value) => {
+                    header.tags = value
+                        .split(',')
+                        .map(|tag| tag.trim())
+                        .filter(|tag| !tag.is_empty())
+                        .map(|tag| tag.to_string())
+                        .collect();
+                }
+                ("preserve-lines", value) => {
+                    header.preserve_lines = parse_bool("preserve-lines", value)?;
+                }
+                ("sidenotes", value) => {
+                    header.sidenotes = Some(parse_bool("sidenotes", value)?);
+                }
+                ("autolink", value) => {
+                    header.autolink = Some(parse_bool("autolink", value)?);
+                }
+                ("typography", value) => {
+                    header.typography = Some(parse_bool("typography", value)?);
+                }
+                ("emoji", value) => {
+                    header.emoji = Some(parse_bool("emoji", value)?);
+                }
+                _ => break,
+            }
+
+            body_start += line.len();
+        }
+
+        // Skip the blank lines separating the header from the body
+        if body_start > 0 {
+            let rest = &content[body_start..];
+            body_start += rest.len() - rest.trim_start_matches(&['\n', '\r'][..]).len();
+        }
+
+        Ok((header, body_start))
+    }
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool, JustTextError<'static>> {
+    match value {
+        "true" | "yes" => Ok(true),
+        "false" | "no" => Ok(false),
+        _ => Err(JustTextError::new(format!(
+            "Expected \"true\" or \"false\" for \"{}\", found \"{}\"",
+            key, value
+        ))),
+    }
+}
+
+fn parse_setting(line: &str) -> Option<(&str, &str)> {
+    let line = line.strip_prefix("% ")?;
+    let colon = line.find(':')?;
+    let key = line[..colon].trim();
+
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_lowercase() || c == '-') {
+        return None;
+    }
+
+    Some((key, line[colon + 1..].trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_header1() {
+        let input = "% tags: rust,  parsing ,\n\nThe body";
+        //           0123456789012345678901234 5 6
+
+        assert_eq!(
+            Header::parse(input),
+            Ok((
+                Header {
+                    tags: vec![String::from("rust"), String::from("parsing")],
+                    ..Header::default()
+                },
+                26
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_header2() {
+        let input = "% of people agree: this is not a header";
+
+        assert_eq!(Header::parse(input), Ok((Header::default(), 0)));
+    }
+
+    #[test]
+    fn parse_header3() {
+        let input = "% tags: paint\n% done: 40%\nBlue";
+
+        assert_eq!(
+            Header::parse(input),
+            Ok((
+                Header {
+                    tags: vec![String::from("paint")],
+                    ..Header::default()
+                },
+                14
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_header4() {
+        let input = "% preserve-lines: yes\n% tags: poems\nRoses are red";
+
+        assert_eq!(
+            Header::parse(input),
+            Ok((
+                Header {
+                    tags: vec![String::from("poems")],
+                    preserve_lines: true,
+                    ..Header::default()
+                },
+                36
+            ))
+        );
+    }
+}
@@ -0,0 +1,280 @@
+use super::common::escape_html;
+
+/// Just enough about a language's syntax to pick out keywords, literals,
+/// strings, numbers, and comments.
+struct Language {
+    names: &'static [&'static str],
+    /// Space-separated
+    keywords: &'static str,
+    /// Space-separated
+    literals: &'static str,
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    strings: &'static [&'static str],
+    char_literals: bool,
+    case_insensitive: bool,
+}
+
+static LANGUAGES: &[Language] = &[
+    Language {
+        names: &["rust", "rs"],
+        keywords: "as async await break const continue crate dyn else enum extern fn for if impl in \
+                   let loop match mod move mut pub ref return self Self static struct super trait \
+                   type unsafe use where while",
+        literals: "true false None Some Ok Err",
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        strings: &["\""],
+        char_literals: true,
+        case_insensitive: false,
+    },
+    Language {
+        names: &["python", "py"],
+        keywords: "and as assert async await break class continue def del elif else except finally \
+                   for from global if import in is lambda nonlocal not or pass raise return try \
+                   while with yield",
+        literals: "True False None",
+        line_comments: &["#"],
+        block_comment: None,
+        strings: &["\"\"\"", "'''", "\"", "'"],
+        char_literals: false,
+        case_insensitive: false,
+    },
+    Language {
+        names: &["javascript", "js", "typescript", "ts", "jsx", "tsx"],
+        keywords: "async await break case catch class const continue default delete do else export \
+                   extends finally for from function if import in instanceof interface let new of \
+                   return switch this throw try type typeof var void while yield",
+        literals: "true false null undefined NaN",
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        strings: &["\"", "'", "`"],
+        char_literals: false,
+        case_insensitive: false,
+    },
+    Language {
+        names: &["c", "h", "cpp", "c++", "hpp", "java", "cs", "csharp"],
+        keywords: "auto break case catch char class const continue default delete do double else \
+                   enum extends extern final float for goto if implements import int long namespace \
+                   new package private protected public return short signed sizeof static struct \
+                   switch template this throw try typedef union unsigned using virtual void \
+                   volatile while",
+        literals: "true false null nullptr NULL",
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        strings: &["\""],
+        char_literals: true,
+        case_insensitive: false,
+    },
+    Language {
+        names: &["go", "golang"],
+        keywords: "break case chan const continue default defer else fallthrough for func go goto \
+                   if import interface map package range return select struct switch type var",
+        literals: "true false nil iota",
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        strings: &["\"", "`"],
+        char_literals: true,
+        case_insensitive: false,
+    },
+    Language {
+        names: &["shell", "sh", "bash", "zsh", "console"],
+        keywords: "case do done elif else esac export fi for function if in local return then until \
+                   while",
+        literals: "true false",
+        line_comments: &["#"],
+        block_comment: None,
+        strings: &["\"", "'"],
+        char_literals: false,
+        case_insensitive: false,
+    },
+    Language {
+        names: &["json"],
+        keywords: "",
+        literals: "true false null",
+        line_comments: &[],
+        block_comment: None,
+        strings: &["\""],
+        char_literals: false,
+        case_insensitive: false,
+    },
+    Language {
+        names: &["toml", "ini"],
+        keywords: "",
+        literals: "true false",
+        line_comments: &["#"],
+        block_comment: None,
+        strings: &["\"\"\"", "'''", "\"", "'"],
+        char_literals: false,
+        case_insensitive: false,
+    },
+    Language {
+        names: &["sql"],
+        keywords: "add alter and as asc by create delete desc distinct drop from group having in \
+                   index inner insert into is join key left limit not on or order primary \
+                   references right select set table union update values where",
+        literals: "true false null",
+        line_comments: &["--"],
+        block_comment: Some(("/*", "*/")),
+        strings: &["'", "\""],
+        char_literals: false,
+        case_insensitive: true,
+    },
+];
+
+/// Highlights `code` by wrapping the interesting parts in `<span>`s with
+/// "hl-*" classes (styled in main.css). Returns `None` if `language` isn't
+/// one we know about.
+pub fn highlight(language: &str, code: &str) -> Option<String> {
+    let language = language.to_lowercase();
+    let language = LANGUAGES
+        .iter()
+        .find(|l| l.names.contains(&language.as_str()))?;
+
+    let mut result = String::new();
+    let mut rest = code;
+
+    while let Some(c) = rest.chars().next() {
+        let (class, len) = next_span(language, rest, c);
+        let (text, remainder) = rest.split_at(len);
+
+        match class {
+            Some(class) => {
+                result.push_str(&format!(
+                    "<span class=\"hl-{}\">{}</span>",
+                    class,
+                    escape_html(text)
+                ));
+            }
+            None => result.push_str(&escape_html(text)),
+        }
+        rest = remainder;
+    }
+
+    Some(result)
+}
+
+/// Classifies the span at the start of `rest` (whose first char is `c`),
+/// returning its class and length in bytes.
+fn next_span(language: &Language, rest: &str, c: char) -> (Option<&'static str>, usize) {
+    if language.line_comments.iter().any(|p| rest.starts_with(p)) {
+        let len = rest.find('\n').unwrap_or(rest.len());
+        return (Some("comment"), len);
+    }
+
+    if let Some((open, close)) = language.block_comment {
+        if let Some(after_open) = rest.strip_prefix(open) {
+            let len = after_open
+                .find(close)
+                .map(|n| open.len() + n + close.len())
+                .unwrap_or(rest.len());
+            return (Some("comment"), len);
+        }
+    }
+
+    if let Some(quote) = language.strings.iter().find(|q| rest.starts_with(*q)) {
+        return (Some("string"), string_len(rest, quote));
+    }
+
+    if language.char_literals && c == '\'' {
+        if let Some(len) = char_literal_len(rest) {
+            return (Some("string"), len);
+        }
+    }
+
+    if c.is_ascii_digit() {
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+            .unwrap_or(rest.len());
+        return (Some("number"), len);
+    }
+
+    if c.is_alphabetic() || c == '_' {
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let word = &rest[..len];
+        let matches = |words: &str| {
+            words.split_whitespace().any(|w| {
+                if language.case_insensitive {
+                    w.eq_ignore_ascii_case(word)
+                } else {
+                    w == word
+                }
+            })
+        };
+
+        if matches(language.keywords) {
+            return (Some("keyword"), len);
+        }
+        if matches(language.literals) {
+            return (Some("literal"), len);
+        }
+        return (None, len);
+    }
+
+    (None, c.len_utf8())
+}
+
+fn string_len(rest: &str, quote: &str) -> usize {
+    let mut chars = rest[quote.len()..].char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if rest[quote.len() + i..].starts_with(quote) {
+            return quote.len() + i + quote.len();
+        }
+    }
+
+    rest.len()
+}
+
+/// Distinguishes 'a' and '\n' from Rust lifetimes like 'a.
+fn char_literal_len(rest: &str) -> Option<usize> {
+    let mut chars = rest.char_indices().skip(1);
+    let (_, c) = chars.next()?;
+    if c == '\\' {
+        chars.next()?;
+    }
+    let (i, c) = chars.next()?;
+
+    if c == '\'' {
+        Some(i + 1)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlight1() {
+        assert_eq!(
+            highlight("rust", "fn main<'a>() { let c = 'x'; } // \"done\""),
+            Some(String::from(
+                "<span class=\"hl-keyword\">fn</span> main&lt;'a&gt;() { \
+                 <span class=\"hl-keyword\">let</span> c = <span class=\"hl-string\">'x'</span>; } \
+                 <span class=\"hl-comment\">// &quot;done&quot;</span>"
+            ))
+        );
+    }
+
+    #[test]
+    fn highlight2() {
+        assert_eq!(
+            highlight("Python", "x = \"a\\\"b\" # 42\ny = 42"),
+            Some(String::from(
+                "x = <span class=\"hl-string\">&quot;a\\&quot;b&quot;</span> \
+                 <span class=\"hl-comment\"># 42</span>\ny = <span class=\"hl-number\">42</span>"
+            ))
+        );
+    }
+
+    #[test]
+    fn highlight3() {
+        assert_eq!(highlight("brainfuck", "+++"), None);
+    }
+}
@@ -0,0 +1,220 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+
+/// What part of an included file to show
+#[derive(Debug, PartialEq)]
+pub enum Selection<'a> {
+    All,
+    /// A (1-based, inclusive) range of lines
+    Lines(usize, usize),
+    /// The lines between "#region name" and "#endregion" markers, which are
+    /// usually written in a comment
+    Region(&'a str),
+}
+
+/// Reads the part of a file that `spec` ("path", "path#L10-L40", or
+/// "path#name") describes, relative to `dir`.
+pub fn read(dir: &Path, spec: &str) -> Result<String, String> {
+    let (path, selection) = parse_spec(spec)?;
+    let code = fs::read_to_string(resolve_path(dir, path)?).map_err(|e| match e.kind() {
+        ErrorKind::NotFound => String::from("no such file"),
+        _ => e.to_string(),
+    })?;
+
+    select(&code, &selection).map(|code| dedent(&code))
+}
+
+/// Joins `path` to `dir`, which is relative to the project's directory. Only
+/// files within the project can be included, so that a note can't show an
+/// arbitrary file from elsewhere on the machine that builds it.
+fn resolve_path(dir: &Path, path: &str) -> Result<PathBuf, String> {
+    let joined = dir.join(path);
+    let mut depth = 0;
+    for component in joined.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => {
+                return Err(String::from("the path must be relative to the note"));
+            }
+            Component::ParentDir if depth == 0 => {
+                return Err(String::from("the file is outside the project"));
+            }
+            Component::ParentDir => depth -= 1,
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+        }
+    }
+
+    Ok(joined)
+}
+
+/// Splits a spec into its path and selection.
+pub fn parse_spec(spec: &str) -> Result<(&str, Selection<'_>), String> {
+    let (path, fragment) = match spec.split_once('#') {
+        Some((path, fragment)) => (path, fragment),
+        None => return Ok((spec, Selection::All)),
+    };
+
+    let lines = fragment.strip_prefix('L').and_then(|range| {
+        let (start, end) = range.split_once('-').unwrap_or((range, range));
+        let start = start.parse::<usize>().ok()?;
+        let end = end.strip_prefix('L').unwrap_or(end).parse::<usize>().ok()?;
+        Some((start, end))
+    });
+
+    match lines {
+        Some((start, end)) if start == 0 || end < start => {
+            Err(format!("\"{}\" isn't a valid range of lines", fragment))
+        }
+        Some((start, end)) => Ok((path, Selection::Lines(start, end))),
+        None if fragment.is_empty() => Err(String::from("expected lines or a region after \"#\"")),
+        None => Ok((path, Selection::Region(fragment))),
+    }
+}
+
+pub fn select(code: &str, selection: &Selection) -> Result<String, String> {
+    let lines = code.lines().collect::<Vec<&str>>();
+
+    match *selection {
+        Selection::All => Ok(code.trim_end_matches('\n').to_string()),
+        Selection::Lines(start, end) => {
+            if end > lines.len() {
+                return Err(format!(
+                    "the file only has {} lines, not {}",
+                    lines.len(),
+                    end
+                ));
+            }
+            Ok(lines[start - 1..end].join("\n"))
+        }
+        Selection::Region(name) => {
+            let start = lines
+                .iter()
+                .position(|line| region_start(line) == Some(name))
+                .ok_or_else(|| format!("there's no region \"{}\"", name))?;
+
+            // Markers of regions nested within this one are left out
+            let mut depth = 0;
+            let mut selected = vec![];
+            for line in &lines[start + 1..] {
+                if region_start(line).is_some() {
+                    depth += 1;
+                } else if line.contains("#endregion") {
+                    if depth == 0 {
+                        return Ok(selected.join("\n"));
+                    }
+                    depth -= 1;
+                } else {
+                    selected.push(*line);
+                }
+            }
+
+            Err(format!("region \"{}\" has no \"#endregion\"", name))
+        }
+    }
+}
+
+fn region_start(line: &str) -> Option<&str> {
+    let (_, name) = line.split_once("#region ")?;
+    Some(name.trim())
+}
+
+/// Removes the indentation (spaces and tabs) that every (non-blank) line has
+/// in common.
+fn dedent(code: &str) -> String {
+    let indent = code
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start_matches(&[' ', '\t'][..]).len())
+        .min()
+        .unwrap_or(0);
+
+    code.lines()
+        .map(|line| line.get(indent..).unwrap_or(""))
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// The language of the code in a file, from its extension.
+pub fn language(path: &str) -> Option<String> {
+    let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+    let language = match extension.as_str() {
+        "rs" => "rust",
+        "py" => "python",
+        "js" | "mjs" | "cjs" | "jsx" => "javascript",
+        "ts" | "tsx" => "typescript",
+        "h" => "c",
+        "cc" | "cxx" | "hpp" => "cpp",
+        "sh" | "bash" | "zsh" => "shell",
+        other => other,
+    };
+
+    Some(language.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_spec1() {
+        assert_eq!(parse_spec("a.rs"), Ok(("a.rs", Selection::All)));
+        assert_eq!(
+            parse_spec("src/a.rs#L10-L40"),
+            Ok(("src/a.rs", Selection::Lines(10, 40)))
+        );
+        assert_eq!(parse_spec("a.rs#L7"), Ok(("a.rs", Selection::Lines(7, 7))));
+        assert_eq!(
+            parse_spec("a.rs#setup"),
+            Ok(("a.rs", Selection::Region("setup")))
+        );
+        assert!(parse_spec("a.rs#L5-L2").is_err());
+        assert!(parse_spec("a.rs#").is_err());
+    }
+
+    #[test]
+    fn select1() {
+        let code = "fn main() {\n    // #region body\n    let x = 1;\n    // #region inner\n    \
+                    let y = 2;\n    // #endregion\n    // #endregion\n}\n";
+
+        assert_eq!(
+            select(code, &Selection::Region("body")).map(|code| dedent(&code)),
+            Ok(String::from("let x = 1;\nlet y = 2;"))
+        );
+        assert_eq!(
+            select(code, &Selection::Lines(1, 3)),
+            Ok(String::from(
+                "fn main() {\n    // #region body\n    let x = 1;"
+            ))
+        );
+        assert!(select(code, &Selection::Lines(1, 9)).is_err());
+        assert!(select(code, &Selection::Region("missing")).is_err());
+    }
+
+    #[test]
+    fn resolve_path1() {
+        let dir = Path::new("notes");
+        assert_eq!(
+            resolve_path(dir, "../src/main.rs"),
+            Ok(PathBuf::from("notes/../src/main.rs"))
+        );
+        assert!(resolve_path(dir, "../../secrets.txt").is_err());
+        assert!(resolve_path(dir, "a/../../../b.txt").is_err());
+        assert!(resolve_path(dir, "/etc/passwd").is_err());
+    }
+
+    #[test]
+    fn dedent1() {
+        assert_eq!(dedent("    a\n\n      b\n"), "a\n\n  b");
+        assert_eq!(dedent("\tx\n\ty"), "x\ny");
+        // Other whitespace is part of the line
+        assert_eq!(dedent("\u{3000}x\n  y"), "\u{3000}x\n  y");
+    }
+
+    #[test]
+    fn language1() {
+        assert_eq!(language("src/main.rs"), Some(String::from("rust")));
+        assert_eq!(language("build.SH"), Some(String::from("shell")));
+        assert_eq!(language("Makefile"), None);
+    }
+}
@@ -1,41 +1,263 @@
 use super::common::Span;
 use std::str::Chars;
 
+#[derive(Clone)]
 pub struct Lexer<'a> {
+    input: &'a str,
     chars: Chars<'a>,
     input_len: usize,
+    at_line_start: bool,
+    /// Whether the current line is a table row, in which "|" separates cells
+    in_table_row: bool,
+    /// Whether the last token was a bullet, which a checkbox can follow
+    after_bullet: bool,
+    /// The length of the fence that opened the code block the lexer is in, if
+    /// it's in one
+    open_fence: Option<usize>,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Lexer<'a> {
         Lexer {
+            input,
             chars: input.chars(),
             input_len: input.len(),
+            at_line_start: true,
+            in_table_row: false,
+            after_bullet: false,
+            open_fence: None,
         }
     }
 
     fn eat_next(&mut self) -> Option<Token> {
+        // Comments are skipped entirely, except within code blocks
+        while self.at_line_start && self.open_fence.is_none() && self.eat_comment() {}
+
         let start = self.current_pos();
-        let next = self.munch();
-        if next.is_none() {
-            return None;
+        let line_marker = if self.at_line_start {
+            self.eat_line_marker()
+        } else if self.after_bullet {
+            self.eat_checkbox()
+        } else {
+            None
+        };
+
+        let kind = match line_marker {
+            Some(kind) => kind,
+            None => self.eat_token(start)?,
+        };
+        // Quoted lines can begin with their own line markers
+        self.at_line_start = matches!(
+            kind,
+            TokenKind::Newline | TokenKind::HardBreak | TokenKind::QuoteMarker
+        );
+        self.after_bullet = matches!(kind, TokenKind::Bullet(_));
+        // Fences within a line, like "Type ``` to start a block", don't open
+        // or close code blocks
+        if let Some(TokenKind::Fence(len)) = line_marker {
+            self.open_fence = match self.open_fence {
+                None => Some(len),
+                Some(open) if len >= open => None,
+                open => open,
+            };
+        }
+        match kind {
+            TokenKind::TableRow => self.in_table_row = true,
+            TokenKind::Newline | TokenKind::HardBreak => self.in_table_row = false,
+            _ => {}
+        }
+
+        let end = self.current_pos();
+        Some(Token {
+            kind,
+            span: (start, end),
+        })
+    }
+
+    /// Markers that are only meaningful at the start of a line.
+    fn eat_line_marker(&mut self) -> Option<TokenKind> {
+        let rest = self.chars.as_str();
+        let line = rest.split('\n').next().unwrap_or("");
+
+        if line == ">" || line.starts_with("> ") {
+            self.eat_bytes(line.len().min(2));
+            return Some(TokenKind::QuoteMarker);
+        }
+
+        let footnote_len = line.strip_prefix('[').and_then(footnote_tail_len);
+        if let Some(len) = footnote_len.map(|len| len + 1) {
+            if line[len..].starts_with(": ") {
+                self.eat_bytes(len + 1);
+                self.eat_while(|c| c == ' ');
+                return Some(TokenKind::FootnoteDef);
+            }
+        }
+
+        if let Some(label) = line.strip_prefix('[') {
+            let len = label.find([']', '[']).unwrap_or(0);
+            if len > 0 && !label.starts_with(&['^', '!'][..]) && label[len..].starts_with("]: ") {
+                self.eat_bytes(len + 3);
+                self.eat_while(|c| c == ' ');
+                return Some(TokenKind::LinkDef);
+            }
+        }
+
+        if let Some(label) = line.strip_prefix("[!") {
+            let len = label.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(0);
+            if len > 0 && label[len..].starts_with(']') {
+                self.eat_bytes("[!]".len() + len);
+                return Some(TokenKind::CalloutMarker);
+            }
+        }
+
+        let hashes = line.len() - line.trim_start_matches('#').len();
+        if (1..=6).contains(&hashes) && line[hashes..].starts_with(' ') {
+            self.eat_while(|c| c == '#');
+            self.eat_while(|c| c == ' ');
+            return Some(TokenKind::Heading(hashes));
+        }
+
+        if line.starts_with('|') {
+            self.eat_bytes(1);
+            return Some(TokenKind::TableRow);
+        }
+
+        if let Some(name) = line
+            .trim_end()
+            .strip_prefix("![[")
+            .and_then(|rest| rest.strip_suffix("]]"))
+        {
+            if !name.trim().is_empty() && !name.contains(['[', ']']) {
+                self.eat_bytes(line.len());
+                return Some(TokenKind::Transclusion);
+            }
+        }
+
+        if is_rule(line) {
+            self.eat_bytes(line.len());
+            return Some(TokenKind::Rule);
+        }
+
+        if line.trim_end() == "[toc]" {
+            self.eat_bytes("[toc]".len());
+            return Some(TokenKind::TocMarker);
         }
 
-        let next = next.unwrap();
+        // An indented fence's token includes its indentation, so that the
+        // indentation can be removed from the code it contains
+        let unindented = line.trim_start_matches(&[' ', '\t'][..]);
+        if unindented.starts_with("```") {
+            self.eat_while(|c| c == ' ' || c == '\t');
+            let first_tick_pos = self.current_pos();
+            return Some(self.eat_backticks(first_tick_pos));
+        }
+
+        let indent = line.len() - unindented.len();
+        if indent > 0 && unindented.starts_with(": ") {
+            self.eat_bytes(indent + 1);
+            self.eat_while(|c| c == ' ');
+            return Some(TokenKind::DefinitionMarker);
+        }
+
+        // As are list markers, so that lists can be nested
+        if ["- ", "* ", "+ "].iter().any(|m| unindented.starts_with(m)) {
+            self.eat_bytes(indent + 2);
+            return Some(TokenKind::Bullet(indent));
+        }
+
+        let digits = unindented.len()
+            - unindented
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .len();
+        if (1..=9).contains(&digits) && unindented[digits..].starts_with(". ") {
+            self.eat_bytes(indent + digits + 2);
+            return Some(TokenKind::Ordinal(indent));
+        }
+
+        None
+    }
+
+    /// A line beginning with "%%" is a comment, as is everything from a line
+    /// that's just "%%" to the next such line (or the end of the note). Unless
+    /// it follows a quote marker, the newline ending a comment is part of it,
+    /// so that comments don't split paragraphs. Following a quote marker, a
+    /// bare "%%" is only a line comment, since no later line could close it.
+    fn eat_comment(&mut self) -> bool {
+        let rest = self.chars.as_str();
+        let line = rest.split('\n').next().unwrap_or("");
+        if !line.trim_start().starts_with("%%") {
+            return false;
+        }
+
+        let at_real_line_start = self.input[..self.current_pos()]
+            .chars()
+            .next_back()
+            .is_none_or(|c| c == '\n');
+        let mut len = line.len();
+        if at_real_line_start && line.trim() == "%%" {
+            let mut lines = rest.split_inclusive('\n').skip(1);
+            len = rest.len();
+            let mut offset = rest.split_inclusive('\n').next().unwrap_or("").len();
+            for next in &mut lines {
+                if next.trim() == "%%" {
+                    len = offset + next.trim_end_matches('\n').len();
+                    break;
+                }
+                offset += next.len();
+            }
+        }
+
+        if at_real_line_start && rest[len..].starts_with('\n') {
+            len += 1;
+        }
+        self.eat_bytes(len);
+
+        true
+    }
+
+    /// "[ ] " or "[x] " following a bullet.
+    fn eat_checkbox(&mut self) -> Option<TokenKind> {
+        let rest = self.chars.as_str();
+        let checked = match rest.get(..4)? {
+            "[ ] " => false,
+            "[x] " | "[X] " => true,
+            _ => return None,
+        };
+        self.eat_bytes(4);
+
+        Some(TokenKind::Checkbox(checked))
+    }
+
+    fn eat_token(&mut self, start: usize) -> Option<TokenKind> {
+        if self.at_word_start() {
+            if let Some(len) = autolink_len(self.chars.as_str()) {
+                self.eat_bytes(len);
+                return Some(TokenKind::Autolink);
+            }
+        }
+
+        let next = self.munch()?;
         let kind = match next {
-            '[' => TokenKind::LBracket,
+            '[' => match footnote_tail_len(self.chars.as_str()) {
+                Some(len) => {
+                    self.eat_bytes(len);
+                    TokenKind::FootnoteRef
+                }
+                None => TokenKind::LBracket,
+            },
             ']' => TokenKind::RBracket,
             '(' => TokenKind::LParen,
             ')' => TokenKind::RParen,
             '\n' => TokenKind::Newline,
+            '|' if self.in_table_row => TokenKind::Pipe,
             '`' => self.eat_backticks(start),
+            '$' => self.eat_dollars(start),
+            '*' => self.eat_stars(start),
+            '\\' => self.eat_escape(),
             _ => self.eat_text(),
         };
-        let end = self.current_pos();
-        Some(Token {
-            kind,
-            span: (start, end),
-        })
+
+        Some(kind)
     }
 
     fn eat_backticks(&mut self, first_tick_pos: usize) -> TokenKind {
@@ -44,20 +266,88 @@ impl<'a> Lexer<'a> {
 
         match count {
             1 => TokenKind::Backtick,
-            3 => TokenKind::TripleBacktick,
+            2 => TokenKind::Text,
+            _ => TokenKind::Fence(count),
+        }
+    }
+
+    /// A "$" can open inline math if it's followed by a non-space, and close it
+    /// if it's preceded by a non-space and not followed by a digit (so "$5 to
+    /// $10" isn't math).
+    fn eat_dollars(&mut self, first_dollar_pos: usize) -> TokenKind {
+        self.eat_while(|c| c == '$');
+        let count = self.current_pos() - first_dollar_pos;
+
+        let before = self.input[..first_dollar_pos].chars().next_back();
+        let after = self.peek();
+        let opens = after.is_some_and(|c| !c.is_whitespace());
+        let closes = before.is_some_and(|c| !c.is_whitespace())
+            && !after.is_some_and(|c| c.is_ascii_digit());
+
+        match count {
+            1 if opens || closes => TokenKind::Dollar { opens, closes },
+            2 => TokenKind::DoubleDollar,
+            _ => TokenKind::Text,
+        }
+    }
+
+    /// "*" or "**" can open emphasis if it's followed by a non-space, and close
+    /// it if it's preceded by a non-space (so "2 * 3" is just text).
+    fn eat_stars(&mut self, first_star_pos: usize) -> TokenKind {
+        self.eat_while(|c| c == '*');
+        let count = self.current_pos() - first_star_pos;
+
+        let before = self.input[..first_star_pos].chars().next_back();
+        let opens = self.peek().is_some_and(|c| !c.is_whitespace());
+        let closes = before.is_some_and(|c| !c.is_whitespace());
+
+        match count {
+            1 | 2 if opens || closes => TokenKind::Star {
+                strong: count == 2,
+                opens,
+                closes,
+            },
+            _ => TokenKind::Text,
+        }
+    }
+
+    fn eat_escape(&mut self) -> TokenKind {
+        match self.peek() {
+            Some('\n') => {
+                self.munch();
+                TokenKind::HardBreak
+            }
+            Some(c) if c.is_ascii_punctuation() => {
+                self.munch();
+                TokenKind::Escape
+            }
             _ => TokenKind::Text,
         }
     }
 
     fn eat_text(&mut self) -> TokenKind {
-        self.eat_while(|c| match c {
-            '[' | ']' | '(' | ')' | '\n' | '`' => false,
-            _ => true,
-        });
+        while let Some(c) = self.peek() {
+            let stop = match c {
+                '[' | ']' | '(' | ')' | '\n' | '`' | '\\' | '$' | '*' => true,
+                '|' => self.in_table_row,
+                // Bare URLs and email addresses are tokens of their own
+                _ => self.at_word_start() && autolink_len(self.chars.as_str()).is_some(),
+            };
+            if stop {
+                break;
+            }
+            self.munch();
+        }
 
         TokenKind::Text
     }
 
+    /// Whether the next char begins a word (including an email address).
+    fn at_word_start(&self) -> bool {
+        let before = self.input[..self.current_pos()].chars().next_back();
+        !before.is_some_and(|c| c.is_alphanumeric() || is_email_char(c))
+    }
+
     fn current_pos(&self) -> usize {
         self.input_len - self.chars.as_str().len()
     }
@@ -70,6 +360,13 @@ impl<'a> Lexer<'a> {
         self.chars.next()
     }
 
+    fn eat_bytes(&mut self, count: usize) {
+        let end = self.current_pos() + count;
+        while self.current_pos() < end {
+            self.munch();
+        }
+    }
+
     fn eat_while<F>(&mut self, pred: F)
     where
         F: Fn(char) -> bool,
@@ -83,6 +380,93 @@ impl<'a> Lexer<'a> {
     }
 }
 
+/// If `s` begins with a URL ("http://", "https://", or "www.") or an email
+/// address, returns its length. Trailing punctuation, and closing parens
+/// without a matching opening paren, aren't part of it.
+fn autolink_len(s: &str) -> Option<usize> {
+    let scheme_len = ["https://", "http://", "www."]
+        .iter()
+        .find(|scheme| {
+            s.get(..scheme.len())
+                .is_some_and(|prefix| prefix.eq_ignore_ascii_case(scheme))
+        })
+        .map(|scheme| scheme.len());
+
+    let mut len = match scheme_len {
+        Some(scheme_len) => {
+            let url_len = s
+                .find(|c: char| c.is_whitespace() || "<>[]`\"|".contains(c))
+                .unwrap_or(s.len());
+            if !s[scheme_len..url_len].starts_with(char::is_alphanumeric) {
+                return None;
+            }
+            url_len
+        }
+        None => email_len(s)?,
+    };
+
+    loop {
+        let url = &s[..len];
+        let last = url.chars().next_back()?;
+        let unbalanced = last == ')' && url.matches(')').count() > url.matches('(').count();
+        if unbalanced || ".,:;!?'*_~".contains(last) {
+            len -= last.len_utf8();
+        } else {
+            return Some(len);
+        }
+    }
+}
+
+/// Whether `line` is three or more "-", "*", or "_"s (all the same, but
+/// possibly separated by spaces), e.g. "---" or "* * *".
+fn is_rule(line: &str) -> bool {
+    let line = line.trim();
+    let c = match line.chars().next() {
+        Some(c @ ('-' | '*' | '_')) => c,
+        _ => return false,
+    };
+
+    line.chars().all(|x| x == c || x == ' ' || x == '\t') && line.matches(c).count() >= 3
+}
+
+fn is_email_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "._%+-".contains(c)
+}
+
+/// The length of the email address that `s` begins with, e.g. "a@b.com".
+fn email_len(s: &str) -> Option<usize> {
+    let local_len = s.find(|c: char| !is_email_char(c)).unwrap_or(s.len());
+    let domain = s[local_len..].strip_prefix('@')?;
+    let domain_len = domain
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '.'))
+        .unwrap_or(domain.len());
+    let domain = domain[..domain_len].trim_end_matches('.');
+
+    // The top-level domain is at least two letters
+    let tld = domain.rsplit('.').next()?;
+    if local_len == 0 || !domain.contains('.') || tld.len() < 2 {
+        return None;
+    }
+    if !tld.chars().all(|c| c.is_ascii_alphabetic()) || domain.contains("..") {
+        return None;
+    }
+
+    Some(local_len + 1 + domain.len())
+}
+
+/// If `s` begins with the "^label]" of a footnote (following its "["), returns
+/// the length of that part.
+fn footnote_tail_len(s: &str) -> Option<usize> {
+    let label = s.strip_prefix('^')?;
+    let len = label.find(|c: char| c == ']' || c == '[' || c.is_whitespace())?;
+
+    if len > 0 && label[len..].starts_with(']') {
+        Some(len + 2)
+    } else {
+        None
+    }
+}
+
 impl<'a> Iterator for Lexer<'a> {
     type Item = Token;
 
@@ -91,13 +475,40 @@ impl<'a> Iterator for Lexer<'a> {
     }
 }
 
-#[derive(Debug, PartialEq)]
+/// A cursor over lexed tokens, which is cheap to clone (for backtracking).
+#[derive(Clone)]
+pub struct Tokens<'t> {
+    tokens: &'t [Token],
+    pos: usize,
+}
+
+impl<'t> Tokens<'t> {
+    pub fn new(tokens: &'t [Token]) -> Tokens<'t> {
+        Tokens { tokens, pos: 0 }
+    }
+
+    pub fn peek(&self) -> Option<&'t Token> {
+        self.tokens.get(self.pos)
+    }
+}
+
+impl<'t> Iterator for Tokens<'t> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.tokens.get(self.pos)?;
+        self.pos += 1;
+        Some(next.clone())
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct Token {
     pub kind: TokenKind,
     pub span: Span,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum TokenKind {
     Text,
     Newline,
@@ -106,7 +517,58 @@ pub enum TokenKind {
     LParen,
     RParen,
     Backtick,
-    TripleBacktick,
+    /// Three or more backticks
+    Fence(usize),
+    /// A backslash followed by an ASCII punctuation char
+    Escape,
+    /// A backslash at the end of a line (including the newline)
+    HardBreak,
+    /// One to six "#"s (and the spaces that follow) at the start of a line
+    Heading(usize),
+    /// "[toc]" on a line by itself
+    TocMarker,
+    /// ">" (and a single space) at the start of a line
+    QuoteMarker,
+    /// "[!label]" at the start of a (quoted) line
+    CalloutMarker,
+    /// "-", "*", or "+" and a space, preceded by the given indentation
+    Bullet(usize),
+    /// "[ ] " (or "[x] " if checked) following a bullet, making it a task
+    Checkbox(bool),
+    /// An indented ":" and a space, which begins the definition of the term on
+    /// the line above
+    DefinitionMarker,
+    /// A number, ".", and a space, preceded by the given indentation
+    Ordinal(usize),
+    /// "|" at the start of a line
+    TableRow,
+    /// A line of "---", "* * *", or the like
+    Rule,
+    /// "![[Note]]" on a line by itself
+    Transclusion,
+    /// "[^label]"
+    FootnoteRef,
+    /// "[^label]: " at the start of a line
+    FootnoteDef,
+    /// "[label]: " at the start of a line
+    LinkDef,
+    /// "$", which delimits inline math
+    Dollar {
+        opens: bool,
+        closes: bool,
+    },
+    /// "$$", which delimits display math
+    DoubleDollar,
+    /// "*" (or "**" if `strong`), which delimits emphasis
+    Star {
+        strong: bool,
+        opens: bool,
+        closes: bool,
+    },
+    /// A bare URL or email address
+    Autolink,
+    /// "|" between the cells of a table row
+    Pipe,
 }
 
 #[cfg(test)]
@@ -153,17 +615,432 @@ text
                 (Text, 5),
                 (RBracket, 1),
                 (LParen, 1),
-                (Text, 15),
+                (Autolink, 15),
                 (RParen, 1),
                 (Newline, 1),
-                (TripleBacktick, 3),
+                (Fence(3), 3),
                 (Newline, 1),
                 (Text, 21),
                 (Newline, 1),
                 (Text, 4),
                 (Newline, 1),
-                (TripleBacktick, 3)
+                (Fence(3), 3)
             ]
         );
     }
+
+    #[test]
+    fn lex2() {
+        let input = "## A heading
+[toc]
+Not # a heading
+#Nor this";
+        assert_eq!(
+            Lexer::new(input).collect::<Vec<Token>>(),
+            toks![
+                (Heading(2), 3),
+                (Text, 9),
+                (Newline, 1),
+                (TocMarker, 5),
+                (Newline, 1),
+                (Text, 15),
+                (Newline, 1),
+                (Text, 9)
+            ]
+        );
+    }
+
+    #[test]
+    fn lex3() {
+        let input = "  ````md
+```
+  ````";
+        assert_eq!(
+            Lexer::new(input).collect::<Vec<Token>>(),
+            toks![
+                (Fence(4), 6),
+                (Text, 2),
+                (Newline, 1),
+                (Fence(3), 3),
+                (Newline, 1),
+                (Fence(4), 6)
+            ]
+        );
+    }
+
+    #[test]
+    fn lex4() {
+        let input = "\\[not a link\\] C:\\dir";
+        assert_eq!(
+            Lexer::new(input).collect::<Vec<Token>>(),
+            toks![
+                (Escape, 2),
+                (Text, 10),
+                (Escape, 2),
+                (Text, 3),
+                (Text, 1),
+                (Text, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn lex5() {
+        let input = "Two\\
+# lines";
+        assert_eq!(
+            Lexer::new(input).collect::<Vec<Token>>(),
+            toks![(Text, 3), (HardBreak, 2), (Heading(1), 2), (Text, 5)]
+        );
+    }
+
+    #[test]
+    fn lex6() {
+        let input = "> [!note] Hi
+> > ok
+>
+- a
+  12. b";
+        assert_eq!(
+            Lexer::new(input).collect::<Vec<Token>>(),
+            toks![
+                (QuoteMarker, 2),
+                (CalloutMarker, 7),
+                (Text, 3),
+                (Newline, 1),
+                (QuoteMarker, 2),
+                (QuoteMarker, 2),
+                (Text, 2),
+                (Newline, 1),
+                (QuoteMarker, 1),
+                (Newline, 1),
+                (Bullet(0), 2),
+                (Text, 1),
+                (Newline, 1),
+                (Ordinal(2), 6),
+                (Text, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn lex7() {
+        let input = "| a | `b` |
+c | d";
+        assert_eq!(
+            Lexer::new(input).collect::<Vec<Token>>(),
+            toks![
+                (TableRow, 1),
+                (Text, 3),
+                (Pipe, 1),
+                (Text, 1),
+                (Backtick, 1),
+                (Text, 1),
+                (Backtick, 1),
+                (Text, 1),
+                (Pipe, 1),
+                (Newline, 1),
+                (Text, 5)
+            ]
+        );
+    }
+
+    #[test]
+    fn lex8() {
+        let input = "a[^1] [^ x]
+[^long-one]: b";
+        assert_eq!(
+            Lexer::new(input).collect::<Vec<Token>>(),
+            toks![
+                (Text, 1),
+                (FootnoteRef, 4),
+                (Text, 1),
+                (LBracket, 1),
+                (Text, 3),
+                (RBracket, 1),
+                (Newline, 1),
+                (FootnoteDef, 13),
+                (Text, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn lex9() {
+        let input = "$x$ $ 5$1 $$";
+        assert_eq!(
+            Lexer::new(input).collect::<Vec<Token>>(),
+            toks![
+                (
+                    Dollar {
+                        opens: true,
+                        closes: false
+                    },
+                    1
+                ),
+                (Text, 1),
+                (
+                    Dollar {
+                        opens: false,
+                        closes: true
+                    },
+                    1
+                ),
+                (Text, 1),
+                (Text, 1),
+                (Text, 2),
+                (
+                    Dollar {
+                        opens: true,
+                        closes: false
+                    },
+                    1
+                ),
+                (Text, 2),
+                (DoubleDollar, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn lex10() {
+        let input = "*a* 2 * 3 **b**";
+        assert_eq!(
+            Lexer::new(input).collect::<Vec<Token>>(),
+            toks![
+                (
+                    Star {
+                        strong: false,
+                        opens: true,
+                        closes: false
+                    },
+                    1
+                ),
+                (Text, 1),
+                (
+                    Star {
+                        strong: false,
+                        opens: false,
+                        closes: true
+                    },
+                    1
+                ),
+                (Text, 3),
+                (Text, 1),
+                (Text, 3),
+                (
+                    Star {
+                        strong: true,
+                        opens: true,
+                        closes: false
+                    },
+                    2
+                ),
+                (Text, 1),
+                (
+                    Star {
+                        strong: true,
+                        opens: false,
+                        closes: true
+                    },
+                    2
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn lex11() {
+        let input = "---\n* * *\n- -\n___ x";
+        assert_eq!(
+            Lexer::new(input).collect::<Vec<Token>>(),
+            toks![
+                (Rule, 3),
+                (Newline, 1),
+                (Rule, 5),
+                (Newline, 1),
+                (Bullet(0), 2),
+                (Text, 1),
+                (Newline, 1),
+                (Text, 5)
+            ]
+        );
+    }
+
+    #[test]
+    fn lex12() {
+        let input = "- [ ] a\n  * [X] b [x] \n- [x]";
+        assert_eq!(
+            Lexer::new(input).collect::<Vec<Token>>(),
+            toks![
+                (Bullet(0), 2),
+                (Checkbox(false), 4),
+                (Text, 1),
+                (Newline, 1),
+                (Bullet(2), 4),
+                (Checkbox(true), 4),
+                (Text, 2),
+                (LBracket, 1),
+                (Text, 1),
+                (RBracket, 1),
+                (Text, 1),
+                (Newline, 1),
+                (Bullet(0), 2),
+                (LBracket, 1),
+                (Text, 1),
+                (RBracket, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn lex13() {
+        let input = "Term\n  :  Def\n: no";
+        assert_eq!(
+            Lexer::new(input).collect::<Vec<Token>>(),
+            toks![
+                (Text, 4),
+                (Newline, 1),
+                (DefinitionMarker, 5),
+                (Text, 3),
+                (Newline, 1),
+                (Text, 4)
+            ]
+        );
+    }
+
+    #[test]
+    fn lex14() {
+        let input = "![[Shared Note]]  \n![[]]\nx ![[No]]";
+        assert_eq!(
+            Lexer::new(input).collect::<Vec<Token>>(),
+            toks![
+                (Transclusion, 18),
+                (Newline, 1),
+                (Text, 1),
+                (LBracket, 1),
+                (LBracket, 1),
+                (RBracket, 1),
+                (RBracket, 1),
+                (Newline, 1),
+                (Text, 3),
+                (LBracket, 1),
+                (LBracket, 1),
+                (Text, 2),
+                (RBracket, 1),
+                (RBracket, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn lex15() {
+        let input = "a\n%% note\n  %%\nhidden\n%%\n```\n%% kept\n```\n> %% q\nb";
+        assert_eq!(
+            Lexer::new(input).collect::<Vec<Token>>(),
+            vec![
+                Token {
+                    kind: Text,
+                    span: (0, 1)
+                },
+                Token {
+                    kind: Newline,
+                    span: (1, 2)
+                },
+                Token {
+                    kind: Fence(3),
+                    span: (25, 28)
+                },
+                Token {
+                    kind: Newline,
+                    span: (28, 29)
+                },
+                Token {
+                    kind: Text,
+                    span: (29, 36)
+                },
+                Token {
+                    kind: Newline,
+                    span: (36, 37)
+                },
+                Token {
+                    kind: Fence(3),
+                    span: (37, 40)
+                },
+                Token {
+                    kind: Newline,
+                    span: (40, 41)
+                },
+                Token {
+                    kind: QuoteMarker,
+                    span: (41, 43)
+                },
+                Token {
+                    kind: Newline,
+                    span: (47, 48)
+                },
+                Token {
+                    kind: Text,
+                    span: (48, 49)
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn lex16() {
+        let input = "Type ``` here.\n\n%% secret\n> %%\n> q";
+        assert_eq!(
+            Lexer::new(input).collect::<Vec<Token>>(),
+            vec![
+                Token {
+                    kind: Text,
+                    span: (0, 5)
+                },
+                Token {
+                    kind: Fence(3),
+                    span: (5, 8)
+                },
+                Token {
+                    kind: Text,
+                    span: (8, 14)
+                },
+                Token {
+                    kind: Newline,
+                    span: (14, 15)
+                },
+                Token {
+                    kind: Newline,
+                    span: (15, 16)
+                },
+                Token {
+                    kind: QuoteMarker,
+                    span: (26, 28)
+                },
+                Token {
+                    kind: Newline,
+                    span: (30, 31)
+                },
+                Token {
+                    kind: QuoteMarker,
+                    span: (31, 33)
+                },
+                Token {
+                    kind: Text,
+                    span: (33, 34)
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn autolink_len1() {
+        assert_eq!(autolink_len("https://a.com/b."), Some(15));
+        assert_eq!(autolink_len("http://a.com/(b))"), Some(16));
+        assert_eq!(autolink_len("WWW.a.com!?"), Some(9));
+        assert_eq!(autolink_len("a.b+c@d-e.co.uk, hi"), Some(15));
+        assert_eq!(autolink_len("https:// a"), None);
+        assert_eq!(autolink_len("a@b"), None);
+        assert_eq!(autolink_len("a@b.c"), None);
+    }
 }
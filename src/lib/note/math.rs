@@ -0,0 +1,613 @@
+use super::common::escape_html;
+
+/// A TeX command that stands for a single identifier or operator.
+struct Symbol {
+    name: &'static str,
+    text: &'static str,
+    /// "mi" for identifiers, "mo" for operators
+    element: &'static str,
+}
+
+macro_rules! symbols {
+    ($($element:ident: $($name:literal => $text:literal),*;)*) => {
+        &[$($(Symbol { name: $name, text: $text, element: stringify!($element) }),*),*]
+    };
+}
+
+static SYMBOLS: &[Symbol] = symbols! {
+    mi: "alpha" => "α", "beta" => "β", "gamma" => "γ", "delta" => "δ", "epsilon" => "ϵ",
+        "varepsilon" => "ε", "zeta" => "ζ", "eta" => "η", "theta" => "θ", "vartheta" => "ϑ",
+        "iota" => "ι", "kappa" => "κ", "lambda" => "λ", "mu" => "μ", "nu" => "ν", "xi" => "ξ",
+        "pi" => "π", "varpi" => "ϖ", "rho" => "ρ", "varrho" => "ϱ", "sigma" => "σ",
+        "varsigma" => "ς", "tau" => "τ", "upsilon" => "υ", "phi" => "ϕ", "varphi" => "φ",
+        "chi" => "χ", "psi" => "ψ", "omega" => "ω", "Gamma" => "Γ", "Delta" => "Δ",
+        "Theta" => "Θ", "Lambda" => "Λ", "Xi" => "Ξ", "Pi" => "Π", "Sigma" => "Σ",
+        "Upsilon" => "Υ", "Phi" => "Φ", "Psi" => "Ψ", "Omega" => "Ω", "infty" => "∞",
+        "partial" => "∂", "nabla" => "∇", "emptyset" => "∅", "hbar" => "ℏ", "ell" => "ℓ",
+        "Re" => "ℜ", "Im" => "ℑ", "aleph" => "ℵ";
+    mo: "pm" => "±", "mp" => "∓", "times" => "×", "div" => "÷", "cdot" => "⋅", "ast" => "∗",
+        "star" => "⋆", "circ" => "∘", "bullet" => "∙", "leq" => "≤", "le" => "≤",
+        "geq" => "≥", "ge" => "≥", "neq" => "≠", "ne" => "≠", "approx" => "≈",
+        "equiv" => "≡", "sim" => "∼", "simeq" => "≃", "cong" => "≅", "propto" => "∝",
+        "ll" => "≪", "gg" => "≫", "in" => "∈", "notin" => "∉", "ni" => "∋",
+        "subset" => "⊂", "supset" => "⊃", "subseteq" => "⊆", "supseteq" => "⊇",
+        "cup" => "∪", "cap" => "∩", "setminus" => "∖", "forall" => "∀", "exists" => "∃",
+        "neg" => "¬", "lnot" => "¬", "land" => "∧", "wedge" => "∧", "lor" => "∨",
+        "vee" => "∨", "to" => "→", "rightarrow" => "→", "leftarrow" => "←", "gets" => "←",
+        "leftrightarrow" => "↔", "Rightarrow" => "⇒", "Leftarrow" => "⇐",
+        "Leftrightarrow" => "⇔", "implies" => "⟹", "iff" => "⟺", "mapsto" => "↦",
+        "uparrow" => "↑", "downarrow" => "↓", "ldots" => "…", "dots" => "…",
+        "cdots" => "⋯", "vdots" => "⋮", "ddots" => "⋱", "prime" => "′", "angle" => "∠",
+        "perp" => "⊥", "parallel" => "∥", "mid" => "∣", "langle" => "⟨", "rangle" => "⟩",
+        "lfloor" => "⌊", "rfloor" => "⌋", "lceil" => "⌈", "rceil" => "⌉", "oplus" => "⊕",
+        "otimes" => "⊗", "sum" => "∑", "prod" => "∏", "coprod" => "∐", "int" => "∫",
+        "iint" => "∬", "oint" => "∮", "bigcup" => "⋃", "bigcap" => "⋂";
+};
+
+/// Functions written upright, like "sin".
+static FUNCTIONS: &str = "sin cos tan cot sec csc arcsin arccos arctan sinh cosh tanh log ln \
+                          exp min max sup inf det gcd lim deg arg dim ker Pr";
+
+/// Operators whose scripts go above and below them in display math.
+static LIMITS: &str = "sum prod coprod bigcup bigcap lim min max sup inf";
+
+static ACCENTS: &[(&str, &str)] = &[
+    ("hat", "^"),
+    ("bar", "¯"),
+    ("overline", "‾"),
+    ("vec", "→"),
+    ("dot", "˙"),
+    ("ddot", "¨"),
+    ("tilde", "~"),
+];
+
+static SPACES: &[(&str, &str)] = &[
+    (",", "0.1667em"),
+    (":", "0.2222em"),
+    (">", "0.2222em"),
+    (";", "0.2778em"),
+    ("!", "-0.1667em"),
+    (" ", "1em"),
+    ("quad", "1em"),
+    ("qquad", "2em"),
+];
+
+/// Commands that apply a font to their argument, which must be plain text.
+static FONTS: &[(&str, &str)] = &[
+    ("mathrm", "normal"),
+    ("mathit", "italic"),
+    ("mathbf", "bold"),
+    ("mathbb", "double-struck"),
+    ("mathcal", "script"),
+];
+
+/// Environments laid out as tables, with the delimiters they're wrapped in.
+static ENVIRONMENTS: &[(&str, &str, &str)] = &[
+    ("matrix", "", ""),
+    ("pmatrix", "(", ")"),
+    ("bmatrix", "[", "]"),
+    ("Bmatrix", "{", "}"),
+    ("vmatrix", "|", "|"),
+    ("cases", "{", ""),
+    ("aligned", "", ""),
+];
+
+#[derive(Debug, PartialEq)]
+pub struct MathError {
+    pub message: String,
+    /// Where in the TeX the error occurred
+    pub offset: usize,
+}
+
+/// Converts a (commonly used) subset of TeX math to MathML.
+pub fn to_mathml(tex: &str, display: bool) -> Result<String, MathError> {
+    let mut parser = Parser {
+        tex,
+        pos: 0,
+        display,
+    };
+    let mut rows = vec![parser.parse_row()?];
+
+    loop {
+        match parser.peek() {
+            None => break,
+            Some('}') => return parser.error("Unmatched \"}\"", parser.pos),
+            Some('&') => return parser.error("\"&\" outside of an environment", parser.pos),
+            _ if parser.at_command("\\") => {
+                parser.pos += 2;
+                rows.push(parser.parse_row()?);
+            }
+            _ if parser.at_command("right") => {
+                return parser.error("\"\\right\" without a matching \"\\left\"", parser.pos);
+            }
+            _ => return parser.error("\"\\end\" without a matching \"\\begin\"", parser.pos),
+        }
+    }
+
+    let body = rows
+        .into_iter()
+        .map(mrow)
+        .collect::<Vec<String>>()
+        .join("<mspace linebreak=\"newline\"/>");
+    let display = if display { " display=\"block\"" } else { "" };
+
+    Ok(format!("<math{}>{}</math>", display, body))
+}
+
+/// Wraps `elements` in an <mrow>, unless there's just one of them.
+fn mrow(elements: Vec<String>) -> String {
+    if elements.len() == 1 {
+        elements.into_iter().next().unwrap()
+    } else {
+        format!("<mrow>{}</mrow>", elements.concat())
+    }
+}
+
+struct Parser<'a> {
+    tex: &'a str,
+    pos: usize,
+    display: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.tex[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    fn error<T>(&self, message: &str, offset: usize) -> Result<T, MathError> {
+        Err(MathError {
+            message: message.to_string(),
+            offset,
+        })
+    }
+
+    /// Whether the input continues with the command `\name`.
+    fn at_command(&self, name: &str) -> bool {
+        match self.rest().strip_prefix('\\') {
+            Some(rest) if rest.starts_with(name) => {
+                let after = &rest[name.len()..];
+                // "\right" isn't the start of "\rightarrow"
+                !name.chars().all(char::is_alphabetic)
+                    || !after.starts_with(|c: char| c.is_ascii_alphabetic())
+            }
+            _ => false,
+        }
+    }
+
+    /// Parses elements until the end of the input, or the end of the current
+    /// group, table cell, or `\left`.
+    fn parse_row(&mut self) -> Result<Vec<String>, MathError> {
+        let mut row = vec![];
+
+        loop {
+            self.skip_whitespace();
+            let at_end = match self.peek() {
+                None | Some('}') | Some('&') => true,
+                _ => self.at_command("\\") || self.at_command("right") || self.at_command("end"),
+            };
+            if at_end {
+                break;
+            }
+
+            let (base, limits) = self.parse_atom()?;
+            row.push(self.parse_scripts(base, limits)?);
+        }
+
+        Ok(row)
+    }
+
+    /// Parses a sub- and/or superscript (if there are any) for `base`.
+    fn parse_scripts(&mut self, base: String, limits: bool) -> Result<String, MathError> {
+        let mut sub: Option<String> = None;
+        let mut sup = None;
+
+        loop {
+            self.skip_whitespace();
+            let start = self.pos;
+            let script = match self.peek() {
+                Some('_') => &mut sub,
+                Some('^') => &mut sup,
+                Some('\'') => {
+                    // A prime is a superscript of its own
+                    self.bump();
+                    let prime = String::from("<mo>′</mo>");
+                    match &mut sup {
+                        Some(sup) => *sup = mrow(vec![sup.clone(), prime]),
+                        None => sup = Some(prime),
+                    }
+                    continue;
+                }
+                _ => break,
+            };
+            if script.is_some() {
+                return self.error("Double subscript or superscript", start);
+            }
+            self.bump();
+            *script = Some(self.parse_argument(start)?);
+        }
+
+        let (under, over) = if limits && self.display {
+            ("munder", "mover")
+        } else {
+            ("msub", "msup")
+        };
+        Ok(match (sub, sup) {
+            (None, None) => base,
+            (Some(sub), None) => format!("<{}>{}{}</{}>", under, base, sub, under),
+            (None, Some(sup)) => format!("<{}>{}{}</{}>", over, base, sup, over),
+            (Some(sub), Some(sup)) => {
+                let both = if limits && self.display {
+                    "munderover"
+                } else {
+                    "msubsup"
+                };
+                format!("<{}>{}{}{}</{}>", both, base, sub, sup, both)
+            }
+        })
+    }
+
+    /// Parses a command's (or script's) argument: a group, or a single char or
+    /// command. `start` is where the command began, for reporting errors.
+    fn parse_argument(&mut self, start: usize) -> Result<String, MathError> {
+        self.skip_whitespace();
+        match self.peek() {
+            None | Some('}') | Some('&') => self.error("Missing argument", start),
+            Some(c) if c.is_ascii_digit() => {
+                self.bump();
+                Ok(format!("<mn>{}</mn>", c))
+            }
+            _ => self.parse_atom().map(|(atom, _)| atom),
+        }
+    }
+
+    /// Parses an argument that must be plain text, such as an environment's
+    /// name, returning the text.
+    fn parse_text_argument(&mut self, start: usize) -> Result<&'a str, MathError> {
+        self.skip_whitespace();
+        if self.bump() != Some('{') {
+            return self.error("Expected \"{\"", start);
+        }
+        let rest = self.rest();
+        match rest.find('}') {
+            Some(len) => {
+                self.pos += len + 1;
+                Ok(&rest[..len])
+            }
+            None => self.error("Unterminated group", start),
+        }
+    }
+
+    /// Parses a single element, returning it and whether it's an operator
+    /// that takes limits.
+    fn parse_atom(&mut self) -> Result<(String, bool), MathError> {
+        let start = self.pos;
+        let c = self.bump().unwrap();
+
+        let atom = match c {
+            '{' => {
+                let row = self.parse_row()?;
+                if self.bump() != Some('}') {
+                    return self.error("Unterminated group", start);
+                }
+                mrow(row)
+            }
+            '\\' => return self.parse_command(start),
+            '_' | '^' => {
+                // A script with nothing before it
+                self.pos = start;
+                return Ok((String::from("<mrow></mrow>"), false));
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let len = self
+                    .rest()
+                    .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                    .unwrap_or(self.rest().len());
+                self.pos += len;
+                format!("<mn>{}</mn>", &self.tex[start..self.pos])
+            }
+            c if c.is_alphabetic() => format!("<mi>{}</mi>", c),
+            '-' => String::from("<mo>−</mo>"),
+            c => format!("<mo>{}</mo>", escape_html(&c.to_string())),
+        };
+
+        Ok((atom, false))
+    }
+
+    /// Parses the command at `start`, whose "\" has been consumed.
+    fn parse_command(&mut self, start: usize) -> Result<(String, bool), MathError> {
+        let len = self
+            .rest()
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(self.rest().len());
+        // Commands are either a word, or a single non-letter like "\,"
+        let len = match len {
+            0 => self.peek().map_or(0, char::len_utf8),
+            len => len,
+        };
+        let name = &self.rest()[..len];
+        self.pos += len;
+
+        if let Some(symbol) = SYMBOLS.iter().find(|s| s.name == name) {
+            let limits = LIMITS.split(' ').any(|l| l == name);
+            // Capital Greek letters are upright
+            let variant = match symbol.text.chars().next() {
+                Some(c) if symbol.element == "mi" && c.is_uppercase() => " mathvariant=\"normal\"",
+                _ => "",
+            };
+            let atom = format!(
+                "<{}{}>{}</{}>",
+                symbol.element, variant, symbol.text, symbol.element
+            );
+            return Ok((atom, limits));
+        }
+        if FUNCTIONS.split_whitespace().any(|f| f == name) {
+            let limits = LIMITS.split(' ').any(|l| l == name);
+            return Ok((format!("<mi>{}</mi>", name), limits));
+        }
+        if let Some((_, width)) = SPACES.iter().find(|(n, _)| *n == name) {
+            return Ok((format!("<mspace width=\"{}\"/>", width), false));
+        }
+        if let Some((_, accent)) = ACCENTS.iter().find(|(n, _)| *n == name) {
+            let base = self.parse_argument(start)?;
+            return Ok((
+                format!("<mover accent=\"true\">{}<mo>{}</mo></mover>", base, accent),
+                false,
+            ));
+        }
+        if let Some((_, variant)) = FONTS.iter().find(|(n, _)| *n == name) {
+            let text = self.parse_text_argument(start)?;
+            return Ok((
+                format!("<mi mathvariant=\"{}\">{}</mi>", variant, escape_html(text)),
+                false,
+            ));
+        }
+
+        let atom = match name {
+            "{" | "}" | "|" | "$" | "%" | "#" | "&" | "_" => {
+                let text = if name == "|" { "∥" } else { name };
+                format!("<mo>{}</mo>", escape_html(text))
+            }
+            "frac" | "dfrac" | "tfrac" => {
+                let numerator = self.parse_argument(start)?;
+                let denominator = self.parse_argument(start)?;
+                format!("<mfrac>{}{}</mfrac>", numerator, denominator)
+            }
+            "sqrt" => {
+                self.skip_whitespace();
+                if self.peek() == Some('[') {
+                    self.bump();
+                    let index = self.parse_until(']', start)?;
+                    let radicand = self.parse_argument(start)?;
+                    format!("<mroot>{}{}</mroot>", radicand, index)
+                } else {
+                    format!("<msqrt>{}</msqrt>", self.parse_argument(start)?)
+                }
+            }
+            "text" | "textrm" => {
+                let text = self.parse_text_argument(start)?;
+                format!("<mtext>{}</mtext>", escape_html(text))
+            }
+            "left" => {
+                let open = self.parse_delimiter(start)?;
+                let row = self.parse_row()?;
+                if !self.at_command("right") {
+                    return self.error("Missing \"\\right\"", start);
+                }
+                let right_start = self.pos;
+                self.pos += "\\right".len();
+                let close = self.parse_delimiter(right_start)?;
+                format!("<mrow>{}{}{}</mrow>", open, row.concat(), close)
+            }
+            "begin" => self.parse_environment(start)?,
+            "" => return self.error("Expected a command after \"\\\"", start),
+            _ => {
+                return self.error(&format!("Unknown command \"\\{}\"", name), start);
+            }
+        };
+
+        Ok((atom, false))
+    }
+
+    /// Parses elements up to `end` (which is consumed).
+    fn parse_until(&mut self, end: char, start: usize) -> Result<String, MathError> {
+        let mut row = vec![];
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(c) if c == end => {
+                    self.bump();
+                    return Ok(mrow(row));
+                }
+                None => return self.error(&format!("Missing \"{}\"", end), start),
+                _ => {
+                    let (base, limits) = self.parse_atom()?;
+                    row.push(self.parse_scripts(base, limits)?);
+                }
+            }
+        }
+    }
+
+    /// Parses the delimiter following `\left` or `\right`, where "." means
+    /// there isn't one.
+    fn parse_delimiter(&mut self, start: usize) -> Result<String, MathError> {
+        self.skip_whitespace();
+        let delimiter = match self.peek() {
+            Some('.') => {
+                self.bump();
+                return Ok(String::new());
+            }
+            Some('\\') => {
+                let (atom, _) = self.parse_atom()?;
+                match atom.strip_prefix("<mo>") {
+                    Some(mo) => mo.trim_end_matches("</mo>").to_string(),
+                    None => return self.error("Expected a delimiter", start),
+                }
+            }
+            Some(c) if "()[]|/".contains(c) => {
+                self.bump();
+                c.to_string()
+            }
+            _ => return self.error("Expected a delimiter", start),
+        };
+
+        Ok(format!(
+            "<mo fence=\"true\" stretchy=\"true\">{}</mo>",
+            delimiter
+        ))
+    }
+
+    /// Parses `\begin{name} ... \end{name}`, whose rows are separated by "\\"
+    /// and cells by "&", as a table.
+    fn parse_environment(&mut self, start: usize) -> Result<String, MathError> {
+        let name = self.parse_text_argument(start)?;
+        let (open, close) = match ENVIRONMENTS.iter().find(|(n, ..)| *n == name) {
+            Some((_, open, close)) => (*open, *close),
+            None => {
+                return self.error(&format!("Unknown environment \"{}\"", name), start);
+            }
+        };
+
+        let mut rows = vec![];
+        let mut cells = vec![];
+        loop {
+            cells.push(format!("<mtd>{}</mtd>", mrow(self.parse_row()?)));
+
+            match self.peek() {
+                Some('&') => {
+                    self.bump();
+                }
+                _ if self.at_command("\\") => {
+                    self.pos += 2;
+                    rows.push(format!("<mtr>{}</mtr>", cells.concat()));
+                    cells.clear();
+                }
+                _ if self.at_command("end") => {
+                    let end_start = self.pos;
+                    self.pos += "\\end".len();
+                    if self.parse_text_argument(end_start)? != name {
+                        return self.error(&format!("Expected \"\\end{{{}}}\"", name), end_start);
+                    }
+                    break;
+                }
+                _ => {
+                    return self.error(&format!("Missing \"\\end{{{}}}\"", name), start);
+                }
+            }
+        }
+        // A trailing "\\" doesn't start another row
+        if cells.len() > 1 || cells[0] != "<mtd><mrow></mrow></mtd>" || rows.is_empty() {
+            rows.push(format!("<mtr>{}</mtr>", cells.concat()));
+        }
+
+        let align = match name {
+            "cases" => " columnalign=\"left\"",
+            "aligned" => " columnalign=\"right left\"",
+            _ => "",
+        };
+        let fence = |d: &str| match d {
+            "" => String::new(),
+            d => format!("<mo fence=\"true\" stretchy=\"true\">{}</mo>", d),
+        };
+
+        Ok(format!(
+            "<mrow>{}<mtable{}>{}</mtable>{}</mrow>",
+            fence(open),
+            align,
+            rows.concat(),
+            fence(close)
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_mathml1() {
+        assert_eq!(
+            to_mathml("x^2 + \\frac{a}{2b} \\leq \\alpha_{i}'", false),
+            Ok(String::from(
+                "<math><mrow><msup><mi>x</mi><mn>2</mn></msup><mo>+</mo>\
+                 <mfrac><mi>a</mi><mrow><mn>2</mn><mi>b</mi></mrow></mfrac><mo>≤</mo>\
+                 <msubsup><mi>α</mi><mi>i</mi><mo>′</mo></msubsup></mrow></math>"
+            ))
+        );
+    }
+
+    #[test]
+    fn to_mathml2() {
+        assert_eq!(
+            to_mathml(
+                "\\sum_{n=1}^\\infty \\left( \\begin{matrix} 1 & 0 \\\\ 0 & 1 \\end{matrix} \\right)",
+                true
+            ),
+            Ok(String::from(
+                "<math display=\"block\"><mrow><munderover><mo>∑</mo>\
+                 <mrow><mi>n</mi><mo>=</mo><mn>1</mn></mrow><mi>∞</mi></munderover>\
+                 <mrow><mo fence=\"true\" stretchy=\"true\">(</mo><mrow><mtable>\
+                 <mtr><mtd><mn>1</mn></mtd><mtd><mn>0</mn></mtd></mtr>\
+                 <mtr><mtd><mn>0</mn></mtd><mtd><mn>1</mn></mtd></mtr></mtable></mrow>\
+                 <mo fence=\"true\" stretchy=\"true\">)</mo></mrow></mrow></math>"
+            ))
+        );
+    }
+
+    #[test]
+    fn to_mathml3() {
+        assert_eq!(
+            to_mathml("a + \\foo", false),
+            Err(MathError {
+                message: String::from("Unknown command \"\\foo\""),
+                offset: 4
+            })
+        );
+        assert_eq!(
+            to_mathml("\\frac{1}{", false),
+            Err(MathError {
+                message: String::from("Unterminated group"),
+                offset: 8
+            })
+        );
+        assert_eq!(
+            to_mathml("x^1^2", false),
+            Err(MathError {
+                message: String::from("Double subscript or superscript"),
+                offset: 3
+            })
+        );
+    }
+    #[test]
+    fn to_mathml4() {
+        // Scripts in a root's index used to be parsed again and again, forever
+        assert_eq!(
+            to_mathml("\\sqrt[^]{x}", false),
+            Err(MathError {
+                message: String::from("Missing \"]\""),
+                offset: 0
+            })
+        );
+        assert_eq!(
+            to_mathml("\\sqrt[n^2]{x}", false),
+            Ok(String::from(
+                "<math><mroot><mi>x</mi><msup><mi>n</mi><mn>2</mn></msup></mroot></math>"
+            ))
+        );
+    }
+}
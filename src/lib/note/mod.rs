@@ -1,9 +1,18 @@
 mod common;
+mod emoji;
+mod header;
+mod highlight;
+mod include;
 mod lexer;
+mod math;
 mod parser;
+mod toc;
 
-use self::parser::parse;
-use super::build::Link;
+use self::header::Header;
+use self::parser::{parse, Options};
+use self::toc::TocEntry;
+use super::build::{GlossaryEntry, IndexEntry, TaskEntry};
+use super::config::Config;
 use super::error::JustTextError;
 use super::meta::Metadatum;
 use crate::assets::NOTE_TEMPLATE;
@@ -14,50 +23,196 @@ use std::error::Error;
 use std::fs;
 use std::path::Path;
 
+const AUTO_TOC_MIN_HEADINGS: usize = 3;
+
 pub struct Note {
     filename: String,
     content: String,
+    header: Header,
+    body_start: usize,
+    parsed: parser::Note,
     pub created: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+    pub pinned: bool,
 }
 
 impl Note {
-    pub fn new(filename: String, content: String) -> Note {
-        Note {
+    pub fn new(
+        filename: String,
+        content: String,
+        updated: DateTime<Utc>,
+    ) -> Result<Note, Box<dyn Error>> {
+        let (header, body_start) = Header::parse(&content)
+            .map_err(|e| JustTextError::new(format!("{}: {}", filename, e)))?;
+
+        Ok(Note {
             filename,
             content,
+            header,
+            body_start,
+            parsed: parser::Note::default(),
             created: Utc::now(),
-        }
+            updated,
+            pinned: false,
+        })
     }
 
-    pub fn reconcile(&mut self, metadata: &Vec<Metadatum>) {
+    pub fn reconcile(&mut self, metadata: &[Metadatum]) {
         if let Some(meta) = metadata.iter().find(|m| m.filename == self.filename) {
             self.created = meta.created;
         }
+        // A note can't have been updated before it was created (this happens when
+        // a note is seen for the first time)
+        if self.updated < self.created {
+            self.updated = self.created;
+        }
     }
 
-    pub fn write(&self, build_dir: &Path) -> Result<(), Box<dyn Error>> {
-        let title = self.generate_title();
-        let date = self.created.format("%b %e %Y").to_string();
+    /// Parses the note's body, which must happen before it's rendered.
+    pub fn parse(&mut self, config: &Config) -> Result<(), Box<dyn Error>> {
+        let options = Options {
+            strict: config.strict,
+            preserve_lines: self.header.preserve_lines,
+            sidenotes: self.header.sidenotes.unwrap_or(config.sidenotes),
+            autolink: self.header.autolink.unwrap_or(true),
+            typography: self.header.typography.unwrap_or(config.typography),
+            emoji: self.header.emoji.unwrap_or(config.emoji),
+        };
 
-        let content = parse(&self.content).map(|n| n.resolve(&self.content));
+        let parsed = parse(self.body(), options);
         // Look into lifetime issue here:
-        if let Err(e) = content {
-            return Err(Box::new(JustTextError::new(format!("{}", e))));
+        if let Err(e) = parsed {
+            let location = match e.pos {
+                Some(pos) => self.locate(pos),
+                None => self.filename.clone(),
+            };
+            return Err(Box::new(JustTextError::new(format!("{}: {}", location, e))));
+        }
+        self.parsed = parsed.unwrap();
+
+        // Files included in code blocks are relative to the note
+        let dir = Path::new(&self.filename)
+            .parent()
+            .unwrap_or_else(|| Path::new(""));
+        let mut code_includes = vec![];
+        for (spec, pos) in self.parsed.code_include_specs(self.body()) {
+            let code = include::read(dir, &spec).map_err(|e| {
+                JustTextError::new(format!(
+                    "{}: Can't include \"{}\": {}",
+                    self.locate(pos),
+                    spec,
+                    e
+                ))
+            })?;
+            code_includes.push((spec, code));
         }
-        let content = content.unwrap();
+        self.parsed.code_includes = code_includes;
+
+        Ok(())
+    }
+
+    /// Describes each piece of markup that was rendered as text while parsing.
+    pub fn warnings(&self) -> Vec<String> {
+        self.parsed
+            .warnings
+            .iter()
+            .map(|warning| format!("{}: {}", self.locate(warning.pos), warning.message))
+            .collect()
+    }
+
+    /// The name of each note that this one includes, and where it's included.
+    pub fn transclusions(&self) -> Vec<(String, usize)> {
+        self.parsed.transclusions(self.body())
+    }
+
+    /// Sets the rendered HTML of each note that this one includes, in the
+    /// order they're included.
+    pub fn set_transcluded(&mut self, transcluded: Vec<String>) {
+        self.parsed.transcluded = transcluded;
+    }
+
+    /// Whether `name` refers to this note, by its title or its file name.
+    pub fn is_named(&self, name: &str) -> bool {
+        self.generate_title() == name || self.get_path_core() == name
+    }
+
+    /// Whether `page` is the note's page, e.g. "My_Note.html".
+    pub fn is_at(&self, page: &str) -> bool {
+        self.get_html_path() == page
+    }
+
+    /// The ids of everything in the note that can be linked to.
+    pub fn anchors(&self) -> Vec<String> {
+        self.parsed.anchors(self.body())
+    }
+
+    /// The destination of each link in the note, and where it's given.
+    pub fn links(&self) -> Vec<(String, usize)> {
+        self.parsed.links(self.body())
+    }
+
+    pub fn render(&self) -> String {
+        self.parsed.resolve(self.body())
+    }
+
+    /// Renders the note as it's included in another (see `resolve_included`).
+    pub fn render_included(&self, transcluded: &[String], id_prefix: &str) -> String {
+        self.parsed
+            .resolve_included(self.body(), transcluded, id_prefix)
+    }
+
+    pub fn write(&self, build_dir: &Path) -> Result<(), Box<dyn Error>> {
+        let html = self.render_page()?;
+        fs::write(build_dir.join(Path::new(&self.get_html_path())), html)?;
+
+        Ok(())
+    }
+
+    /// The note's page, rendered with `note.html`.
+    fn render_page(&self) -> Result<String, Box<dyn Error>> {
+        let title = self.generate_title();
+        let date = self.created.format("%b %e %Y").to_string();
+        let content = self.render();
+        let toc = self.parsed.toc(self.body());
+        // Long notes get a table of contents at the top, unless one was placed
+        // explicitly with "[toc]"
+        let show_toc = !self.parsed.has_toc_marker() && toc_size(&toc) >= AUTO_TOC_MIN_HEADINGS;
 
         let html = Handlebars::new().render_template(
             NOTE_TEMPLATE,
             &json!({
                 "title": title,
                 "date": date,
+                "tags": self.header.tags,
+                "show_toc": show_toc,
+                "toc": toc,
                 "content": content
             }),
         )?;
 
-        fs::write(build_dir.join(Path::new(&self.get_html_path())), html)?;
+        Ok(html)
+    }
 
-        Ok(())
+    fn body(&self) -> &str {
+        &self.content[self.body_start..]
+    }
+
+    /// Describes a position in the body as "file:line:column".
+    pub fn locate(&self, pos: usize) -> String {
+        let (line, column) = self.line_and_column(pos);
+        format!("{}:{}:{}", self.filename, line, column)
+    }
+
+    /// Converts a position in the body into a (1-based) line and column in the
+    /// note's file.
+    fn line_and_column(&self, pos: usize) -> (usize, usize) {
+        let before = &self.content[..self.body_start + pos];
+        let line_start = before.rfind('\n').map(|n| n + 1).unwrap_or(0);
+
+        (
+            before.matches('\n').count() + 1,
+            before[line_start..].chars().count() + 1,
+        )
     }
 
     fn get_html_path(&self) -> String {
@@ -69,12 +224,62 @@ impl Note {
         core.replace("_", " ")
     }
 
-    pub fn generate_link(&self) -> Link {
+    pub fn generate_index_entry(&self) -> IndexEntry {
         let title = self.generate_title();
         let href = self.get_path_core().replace("\"", "&quot;");
         let href = format!("./{}.html", href);
 
-        Link { href, title }
+        IndexEntry {
+            href,
+            title,
+            created: self.created,
+            updated: self.updated,
+            word_count: self.parsed.word_count,
+            tags: self.header.tags.clone(),
+            pinned: self.pinned,
+        }
+    }
+
+    /// Describes each of the note's tasks that isn't done.
+    pub fn generate_task_entries(&self) -> Vec<TaskEntry> {
+        let title = self.generate_title();
+        let note_href = format!("./{}.html", self.get_path_core().replace("\"", "&quot;"));
+
+        self.parsed
+            .tasks(self.body())
+            .into_iter()
+            .enumerate()
+            .filter(|(_, task)| !task.done)
+            .map(|(i, task)| TaskEntry {
+                filename: self.filename.clone(),
+                line: self.line_and_column(task.pos).0,
+                note_title: title.clone(),
+                href: format!("{}#task-{}", note_href, i + 1),
+                note_href: note_href.clone(),
+                text: task.text,
+            })
+            .collect()
+    }
+
+    /// Describes each term defined in the note.
+    pub fn generate_glossary_entries(&self) -> Vec<GlossaryEntry> {
+        let title = self.generate_title();
+        let note_href = format!("./{}.html", self.get_path_core().replace("\"", "&quot;"));
+
+        self.parsed
+            .terms(self.body())
+            .into_iter()
+            .map(|term| GlossaryEntry {
+                term: term.term,
+                definitions: term.definitions,
+                note_title: title.clone(),
+                href: format!("{}#{}", note_href, term.id),
+            })
+            .collect()
+    }
+
+    pub fn generate_permalink(&self, base_url: &str) -> String {
+        format!("{}/{}", base_url, percent_encode(&self.get_html_path()))
     }
 
     fn get_path_core(&self) -> &str {
@@ -86,7 +291,64 @@ impl Note {
     pub fn to_metadatum(&self) -> Metadatum {
         Metadatum {
             filename: self.filename.clone(),
-            created: self.created.clone(),
+            created: self.created,
+        }
+    }
+}
+
+fn toc_size(toc: &[TocEntry]) -> usize {
+    toc.iter().map(|entry| 1 + toc_size(&entry.children)).sum()
+}
+
+/// Encodes each byte of `s` that can't appear as is in a URL's path segment.
+fn percent_encode(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                result.push(byte as char)
+            }
+            _ => result.push_str(&format!("%{:02X}", byte)),
         }
     }
+    result
+}
+
+/// Parses `content` as the note `filename`, created and last updated at the
+/// start of the given day of January 2020.
+#[cfg(test)]
+pub fn test_note(filename: &str, content: &str, day: u32) -> Note {
+    let date = format!("2020-01-{:02}T00:00:00Z", day)
+        .parse::<DateTime<Utc>>()
+        .unwrap();
+    let mut note = Note::new(filename.to_string(), content.to_string(), date).unwrap();
+    note.created = date;
+    note.parse(&Config::default()).unwrap();
+    note
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_page1() {
+        let note = test_note(
+            "notes/A.txt",
+            "# One & Two\n\n## Sub\n\n### Deep\n\n# Three",
+            1,
+        );
+        let html = note.render_page().unwrap();
+
+        assert!(html.contains(
+            "<nav class=\"toc\"><ul><li><a href=\"#one-two\">One &amp; Two</a>\
+             <ul><li><a href=\"#sub\">Sub</a><ul><li><a href=\"#deep\">Deep</a></li></ul></li></ul>\
+             </li><li><a href=\"#three\">Three</a></li></ul></nav>"
+        ));
+
+        // A "[toc]" marker replaces the one at the top
+        let note = test_note("notes/A.txt", "# One\n\n[toc]\n\n# Two\n\n# Three", 1);
+        let html = note.render_page().unwrap();
+        assert_eq!(html.matches("<nav class=\"toc\">").count(), 1);
+    }
 }
@@ -1,11 +1,161 @@
-use super::common::Span;
-use super::lexer::{Lexer, TokenKind};
+use super::common::{escape_html, smarten, unescape, Span};
+use super::emoji::{emojify, lookup, shortcodes};
+use super::highlight::highlight;
+use super::include::language;
+use super::lexer::{Lexer, Token, TokenKind, Tokens};
+use super::math::to_mathml;
+use super::toc::{build_toc, render_toc, slugify, TocEntry, TocHeading};
 use crate::lib::error::JustTextError;
-use std::iter::Peekable;
+use std::cell::Cell;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Default)]
 pub struct Note {
-    paragraphs: Vec<Paragraph>,
+    blocks: Vec<Block>,
+    /// In the order they're numbered, i.e. the order of their first references
+    footnotes: Vec<Footnote>,
+    sidenotes: bool,
+    typography: bool,
+    emoji: bool,
+    /// The rendered HTML of each note that this one includes, in the order
+    /// they're included
+    pub transcluded: Vec<String>,
+    /// The code included from each file that a code block includes, by the
+    /// block's "path#selection" spec
+    pub code_includes: Vec<(String, String)>,
+    /// The number of words in the note, not counting comments
+    pub word_count: usize,
+    pub warnings: Vec<Warning>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Options {
+    /// Report malformed markup as an error, rather than rendering it as text
+    pub strict: bool,
+    /// Render every line break within a paragraph as a hard break
+    pub preserve_lines: bool,
+    /// Render footnotes in the margin, rather than at the end of the note
+    pub sidenotes: bool,
+    /// Link bare URLs and email addresses
+    pub autolink: bool,
+    /// Render straight quotes, "--", "---", and "..." as curly quotes, dashes,
+    /// and ellipses
+    pub typography: bool,
+    /// Replace shortcodes like ":tada:" with emoji
+    pub emoji: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            strict: false,
+            preserve_lines: false,
+            sidenotes: false,
+            autolink: true,
+            typography: false,
+            emoji: true,
+        }
+    }
+}
+
+/// Markup that couldn't be parsed, and was rendered as text instead.
+#[derive(Debug, PartialEq)]
+pub struct Warning {
+    pub message: String,
+    pub pos: usize,
+}
+
+struct Context<'c> {
+    content: &'c str,
+    options: Options,
+    warnings: Vec<Warning>,
+    /// How many blockquotes the tokens being parsed are nested in
+    quote_depth: usize,
+    footnotes: Vec<Footnote>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+struct Footnote {
+    label: String,
+    /// Where the footnote is first referenced
+    pos: usize,
+    refs: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Block {
+    Paragraph(Paragraph),
+    Heading {
+        level: usize,
+        parts: Vec<TextElement>,
+    },
+    Toc,
+    /// A horizontal rule. Those at the top level split the note into sections.
+    Rule,
+    /// "![[name]]", which includes the note called `name`
+    Transclusion {
+        name: Span,
+    },
+    /// A blockquote, or a callout if it begins with a "[!label]" marker, in
+    /// which case `title` is the rest of that line
+    Quote {
+        callout: Option<Span>,
+        title: Vec<TextElement>,
+        blocks: Vec<Block>,
+    },
+    List {
+        ordered: bool,
+        items: Vec<ListItem>,
+    },
+    Table(Table),
+    DefinitionList(Vec<Definition>),
+    /// The URL that reference links labeled `label` link to
+    LinkDef {
+        label: Span,
+        href: Span,
+    },
+    /// The definition of the footnote whose label is `label`, which is
+    /// rendered wherever footnotes are (not where it's defined)
+    FootnoteDef {
+        label: Span,
+        parts: Vec<TextElement>,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Table {
+    alignments: Vec<Alignment>,
+    header: Vec<TableCell>,
+    rows: Vec<Vec<TableCell>>,
+}
+
+type TableCell = Vec<TextElement>;
+
+/// Set by colons in a table's separator row, e.g. ":---:" for `Center`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Alignment {
+    Default,
+    Left,
+    Center,
+    Right,
+}
+
+/// A term, and each of the indented ": " lines beneath it
+#[derive(Debug, PartialEq)]
+pub struct Definition {
+    term: Vec<TextElement>,
+    definitions: Vec<Vec<TextElement>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ListItem {
+    /// Whether the item is a task (whose bullet is followed by "[ ]" or "[x]"),
+    /// and if so, whether it's done
+    done: Option<bool>,
+    /// Where the item's bullet starts
+    pos: usize,
+    parts: Vec<TextElement>,
+    /// Lists nested beneath the item
+    children: Vec<Block>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -16,309 +166,2663 @@ pub struct Paragraph {
 #[derive(Debug, PartialEq)]
 pub enum TextElement {
     Text(Span),
-    Link { title: Span, href: Span },
+    /// `tooltip` is the link's (optional) title attribute
+    Link {
+        title: Vec<TextElement>,
+        href: Span,
+        tooltip: Option<Span>,
+    },
+    /// A link to the URL defined for `label` elsewhere in the note. These are
+    /// replaced with `Link`s once the whole note has been parsed, or with
+    /// their `span` as text if `label` isn't defined.
+    RefLink {
+        title: Vec<TextElement>,
+        label: Span,
+        span: Span,
+    },
     Mono(Span),
-    BlockMono(Span),
+    /// "*emphasis*", or "**strong emphasis**"
+    Emphasis {
+        strong: bool,
+        parts: Vec<TextElement>,
+    },
+    /// A bare URL or email address
+    Autolink(Span),
+    /// `number` is the footnote's (1-based) number, and `occurrence` counts
+    /// the references to it so far, including this one
+    FootnoteRef {
+        span: Span,
+        number: usize,
+        occurrence: usize,
+    },
+    /// Inline ("$...$") or display ("$$...$$") math, converted to MathML
+    Math {
+        tex: Span,
+        mathml: String,
+    },
+    /// The end of a line within a paragraph. A hard break is rendered as <br>.
+    LineBreak {
+        span: Span,
+        hard: bool,
+    },
+    /// `info` is whatever follows the opening fence on its line (e.g. "rust"),
+    /// `indent` is the number of whitespace chars preceding the fence, and
+    /// `quote_depth` is the number of ">" markers preceding each line
+    BlockMono {
+        info: Option<Span>,
+        code: Span,
+        indent: usize,
+        quote_depth: usize,
+    },
 }
 
 impl Note {
     pub fn resolve(&self, content: &str) -> String {
-        let mut result = String::new();
-        for paragraph in &self.paragraphs {
-            result.push_str(&format!("<p>{}</p>", paragraph.resolve(content)));
+        self.resolve_included(content, &self.transcluded, "")
+    }
+
+    /// Renders the note as it's included in another, with `id_prefix` added
+    /// to each of its ids (and links to them), so that they don't clash with
+    /// the other note's, and its own includes rendered as `transcluded`.
+    pub fn resolve_included(
+        &self,
+        content: &str,
+        transcluded: &[String],
+        id_prefix: &str,
+    ) -> String {
+        let mut definitions = vec![];
+        collect_footnote_defs(&self.blocks, content, &mut definitions);
+        let footnotes = self
+            .footnotes
+            .iter()
+            .map(|footnote| {
+                definitions
+                    .iter()
+                    .find(|(label, ..)| *label == footnote.label)
+                    .map(|(_, _, parts)| *parts)
+            })
+            .collect();
+
+        let mut headings = self.headings(content);
+        let mut terms = self.terms(content);
+        for id in headings
+            .iter_mut()
+            .map(|h| &mut h.id)
+            .chain(terms.iter_mut().map(|t| &mut t.id))
+        {
+            id.insert_str(0, id_prefix);
+        }
+
+        let r = Resolver {
+            content,
+            id_prefix,
+            headings,
+            terms,
+            terms_resolved: Cell::new(0),
+            headings_resolved: Cell::new(0),
+            tasks_resolved: Cell::new(0),
+            transclusions_resolved: Cell::new(0),
+            footnotes,
+            sidenotes: self.sidenotes,
+            typography: self.typography,
+            emoji: self.emoji,
+            transcluded,
+            code_includes: &self.code_includes,
+        };
+
+        let mut result = self.resolve_sections(&r);
+        if !self.sidenotes {
+            result.push_str(&self.resolve_footnotes(&r));
         }
         result
     }
-}
 
-impl Paragraph {
-    fn resolve(&self, content: &str) -> String {
-        let mut result = String::new();
-        for part in &self.parts {
-            result.push_str(&part.resolve(content));
-            result.push(' ');
+    /// Rules at the top level split the note into sections, which are anchored
+    /// as "#section-1" and so on. Notes without them are rendered as is.
+    fn resolve_sections(&self, r: &Resolver) -> String {
+        if !self.blocks.contains(&Block::Rule) {
+            return resolve_blocks(&self.blocks, r);
         }
-        result
+
+        self.sections()
+            .into_iter()
+            .enumerate()
+            .map(|(i, blocks)| {
+                format!(
+                    "<section class=\"note-section\" id=\"{}section-{}\">{}</section>",
+                    r.id_prefix,
+                    i + 1,
+                    resolve_blocks(blocks, r)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("<hr>")
     }
-}
 
-impl TextElement {
-    fn resolve(&self, content: &str) -> String {
-        match self {
-            TextElement::Text((s, e)) => content[*s..*e].to_string(),
-            TextElement::Link { title, href } => {
-                let href = &content[href.0..href.1];
-                let title = &content[title.0..title.1];
-                format!("<a href=\"{}\">{}</a>", href, title)
-            }
-            TextElement::Mono((s, e)) => {
-                let slice = &content[*s..*e].trim();
-                format!("<span class=\"mono\">{}</span>", slice)
-            }
-            TextElement::BlockMono((s, e)) => {
-                let slice = &content[*s..*e].trim();
-                format!("<pre>{}</pre>", slice)
-            }
+    /// The blocks of each section that top-level rules split the note into.
+    fn sections(&self) -> Vec<&[Block]> {
+        self.blocks
+            .split(|block| *block == Block::Rule)
+            // Definitions aren't rendered where they are, so they don't make a
+            // section on their own
+            .filter(|blocks| {
+                blocks.iter().any(|block| {
+                    !matches!(block, Block::LinkDef { .. } | Block::FootnoteDef { .. })
+                })
+            })
+            .collect()
+    }
+
+    /// The list of footnotes at the end of the note, each with a link back to
+    /// each of its references.
+    fn resolve_footnotes(&self, r: &Resolver) -> String {
+        let mut items = String::new();
+        for (i, footnote) in self.footnotes.iter().enumerate() {
+            let number = i + 1;
+            let parts = match r.footnotes[i] {
+                Some(parts) => parts,
+                None => continue,
+            };
+
+            let back_links = (1..=footnote.refs)
+                .map(|occurrence| {
+                    format!(
+                        "<a class=\"footnote-back\" href=\"#{}{}\" aria-label=\"Back to reference\">\
+                         \u{21a9}{}</a>",
+                        r.id_prefix,
+                        footnote_ref_id(number, occurrence),
+                        if occurrence > 1 {
+                            format!("<sup>{}</sup>", occurrence)
+                        } else {
+                            String::new()
+                        }
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join(" ");
+            items.push_str(&format!(
+                "<li id=\"{}fn-{}\">{} {}</li>",
+                r.id_prefix,
+                number,
+                resolve_parts(parts, r).trim(),
+                back_links
+            ));
+        }
+
+        if items.is_empty() {
+            String::new()
+        } else {
+            format!("<section class=\"footnotes\"><ol>{}</ol></section>", items)
+        }
+    }
+
+    pub fn toc(&self, content: &str) -> Vec<TocEntry> {
+        build_toc(&self.headings(content))
+    }
+
+    /// The note's tasks, in order. The nth is anchored as "#task-n".
+    pub fn tasks(&self, content: &str) -> Vec<Task> {
+        let mut tasks = vec![];
+        collect_tasks(&self.blocks, content, &mut tasks);
+        tasks
+    }
+
+    /// The name of each note that this one includes, and where it's included.
+    pub fn transclusions(&self, content: &str) -> Vec<(String, usize)> {
+        let mut transclusions = vec![];
+        collect_transclusions(&self.blocks, content, &mut transclusions);
+        transclusions
+    }
+
+    /// The spec of each code block that includes a file ("```include spec"),
+    /// and where it's given.
+    pub fn code_include_specs(&self, content: &str) -> Vec<(String, usize)> {
+        let mut specs = vec![];
+        collect_code_include_specs(&self.blocks, content, &mut specs);
+        specs
+    }
+
+    /// The terms defined in the note's definition lists, in order.
+    pub fn terms(&self, content: &str) -> Vec<Term> {
+        let mut terms = vec![];
+        collect_terms(&self.blocks, content, &mut terms);
+        terms
+    }
+
+    pub fn has_toc_marker(&self) -> bool {
+        self.blocks.contains(&Block::Toc)
+    }
+
+    /// The ids of everything in the note that can be linked to, e.g. from
+    /// another note as "Note.html#id".
+    pub fn anchors(&self, content: &str) -> Vec<String> {
+        let mut anchors = self.generated_ids(content);
+        anchors.extend(self.headings(content).into_iter().map(|h| h.id));
+        anchors
+    }
+
+    /// The destination of each link in the note, and where it's given.
+    pub fn links(&self, content: &str) -> Vec<(String, usize)> {
+        let mut links = vec![];
+        collect_links(&self.blocks, content, &mut links);
+        links
+    }
+
+    /// Collects each heading's level, title, and a unique id derived from its
+    /// title.
+    fn headings(&self, content: &str) -> Vec<TocHeading> {
+        let mut headings = vec![];
+        collect_headings(
+            &self.blocks,
+            content,
+            self,
+            &self.generated_ids(content),
+            &mut headings,
+        );
+        headings
+    }
+
+    /// The ids of the note's sections, tasks, footnotes, and terms, which
+    /// headings' ids mustn't clash with.
+    fn generated_ids(&self, content: &str) -> Vec<String> {
+        let mut ids = vec![];
+        if self.blocks.contains(&Block::Rule) {
+            ids.extend((1..=self.sections().len()).map(|n| format!("section-{}", n)));
         }
+        ids.extend((1..=self.tasks(content).len()).map(|n| format!("task-{}", n)));
+        for (i, footnote) in self.footnotes.iter().enumerate() {
+            ids.push(format!("fn-{}", i + 1));
+            ids.extend((1..=footnote.refs).map(|occurrence| footnote_ref_id(i + 1, occurrence)));
+        }
+        ids.extend(self.terms(content).into_iter().map(|term| term.id));
+        ids
     }
 }
 
-pub fn parse(input: &str) -> Result<Note, JustTextError> {
-    parse_note(&mut Lexer::new(input).peekable())
+/// A term from a definition list, with the id it's anchored by and its
+/// definitions (as plain text).
+#[derive(Debug, PartialEq)]
+pub struct Term {
+    pub id: String,
+    pub term: String,
+    pub definitions: Vec<String>,
 }
 
-fn parse_note<'a>(tokens: &mut Peekable<Lexer<'a>>) -> Result<Note, JustTextError<'a>> {
-    let mut paragraphs = Vec::new();
+/// A task list item, which `text` describes.
+#[derive(Debug, PartialEq)]
+pub struct Task {
+    pub pos: usize,
+    pub text: String,
+    pub done: bool,
+}
 
-    while let Some(_) = tokens.peek() {
-        match parse_paragraph(tokens) {
-            Ok(p) => {
-                paragraphs.push(p);
-            }
-            Err(e) => {
-                return Err(e);
+/// What's needed to resolve a note's blocks, besides the blocks themselves.
+struct Resolver<'a> {
+    content: &'a str,
+    /// Added to each id, which differs for each time a note is included
+    id_prefix: &'a str,
+    headings: Vec<TocHeading>,
+    /// How many of `headings` have been resolved so far
+    headings_resolved: Cell<usize>,
+    /// How many tasks have been resolved so far, which numbers their anchors
+    tasks_resolved: Cell<usize>,
+    /// How many of `transcluded` have been resolved so far
+    transclusions_resolved: Cell<usize>,
+    terms: Vec<Term>,
+    /// How many of `terms` have been resolved so far
+    terms_resolved: Cell<usize>,
+    /// The definition of each footnote, by number, if it has one
+    footnotes: Vec<Option<&'a [TextElement]>>,
+    sidenotes: bool,
+    typography: bool,
+    emoji: bool,
+    transcluded: &'a [String],
+    code_includes: &'a [(String, String)],
+}
+
+fn footnote_ref_id(number: usize, occurrence: usize) -> String {
+    match occurrence {
+        1 => format!("fnref-{}", number),
+        _ => format!("fnref-{}-{}", number, occurrence),
+    }
+}
+
+/// Collects each footnote definition's label, position, and text.
+fn collect_footnote_defs<'a>(
+    blocks: &'a [Block],
+    content: &str,
+    definitions: &mut Vec<(String, usize, &'a [TextElement])>,
+) {
+    for block in blocks {
+        match block {
+            Block::Quote { blocks, .. } => collect_footnote_defs(blocks, content, definitions),
+            Block::FootnoteDef { label, parts } => {
+                definitions.push((content[label.0..label.1].to_string(), label.0, parts));
             }
+            _ => {}
         }
     }
-
-    Ok(Note { paragraphs })
 }
 
-fn parse_paragraph<'a>(tokens: &mut Peekable<Lexer<'a>>) -> Result<Paragraph, JustTextError<'a>> {
-    let mut parts = Vec::new();
-
-    while let Some(t) = tokens.peek() {
-        // Todo: Is there a nicer way to handle this?
-        if t.kind == TokenKind::Newline {
-            tokens.next();
-            if let Some(t) = tokens.peek() {
-                if t.kind == TokenKind::Newline {
-                    tokens.next();
-                    return Ok(Paragraph { parts });
+fn resolve_blocks(blocks: &[Block], r: &Resolver) -> String {
+    let content = r.content;
+    let mut result = String::new();
+    for block in blocks {
+        match block {
+            Block::Paragraph(paragraph) => {
+                result.push_str(&paragraph.resolve(r));
+            }
+            Block::Heading { parts, .. } => {
+                let heading = &r.headings[r.headings_resolved.get()];
+                r.headings_resolved.set(r.headings_resolved.get() + 1);
+                // The note's title is the page's only <h1>
+                let tag = format!("h{}", (heading.level + 1).min(6));
+                result.push_str(&format!(
+                    "<{} id=\"{}\">{}<a class=\"permalink\" href=\"#{}\" \
+                     aria-label=\"Link to this section\">¶</a></{}>",
+                    tag,
+                    heading.id,
+                    resolve_parts(parts, r).trim(),
+                    heading.id,
+                    tag
+                ));
+            }
+            Block::Toc => {
+                result.push_str(&render_toc(&build_toc(&r.headings)));
+            }
+            Block::Rule => {
+                result.push_str("<hr>");
+            }
+            Block::Transclusion { .. } => {
+                let i = r.transclusions_resolved.get();
+                r.transclusions_resolved.set(i + 1);
+                if let Some(html) = r.transcluded.get(i) {
+                    result.push_str(&format!("<div class=\"transclusion\">{}</div>", html));
+                }
+            }
+            Block::Quote {
+                callout,
+                title,
+                blocks,
+            } => {
+                let inner = resolve_blocks(blocks, r);
+                match callout {
+                    Some((s, e)) => {
+                        let label = content[*s..*e].to_lowercase();
+                        let title = match resolve_parts(title, r).trim() {
+                            "" => capitalize(&label),
+                            title => title.to_string(),
+                        };
+                        result.push_str(&format!(
+                            "<aside class=\"callout callout-{}\" role=\"note\">\
+                             <p class=\"callout-title\">{}</p>{}</aside>",
+                            label, title, inner
+                        ));
+                    }
+                    None => result.push_str(&format!("<blockquote>{}</blockquote>", inner)),
+                }
+            }
+            Block::List { ordered, items } => {
+                result.push_str(&resolve_list(*ordered, items, r));
+            }
+            Block::Table(table) => {
+                result.push_str(&table.resolve(r));
+            }
+            Block::DefinitionList(definitions) => {
+                result.push_str("<dl>");
+                for definition in definitions {
+                    let id = &r.terms[r.terms_resolved.get()].id;
+                    r.terms_resolved.set(r.terms_resolved.get() + 1);
+                    result.push_str(&format!(
+                        "<dt id=\"{}\">{}</dt>",
+                        id,
+                        resolve_parts(&definition.term, r).trim()
+                    ));
+                    for parts in &definition.definitions {
+                        result.push_str(&format!("<dd>{}</dd>", resolve_parts(parts, r).trim()));
+                    }
                 }
+                result.push_str("</dl>");
             }
+            Block::FootnoteDef { .. } | Block::LinkDef { .. } => {}
         }
 
-        if tokens.peek().is_none() {
-            return Ok(Paragraph { parts });
+        // Sidenotes follow the block that first references them
+        if r.sidenotes {
+            for part in block
+                .own_parts()
+                .into_iter()
+                .flat_map(TextElement::descendants)
+            {
+                if let TextElement::FootnoteRef {
+                    number,
+                    occurrence: 1,
+                    ..
+                } = part
+                {
+                    if let Some(parts) = r.footnotes[number - 1] {
+                        result.push_str(&format!(
+                            "<aside class=\"sidenote\" id=\"{}fn-{}\"><sup>{}</sup> {}</aside>",
+                            r.id_prefix,
+                            number,
+                            number,
+                            resolve_parts(parts, r).trim()
+                        ));
+                    }
+                }
+            }
         }
+    }
+    result
+}
 
-        match parse_text_element(tokens) {
-            Ok(te) => {
-                parts.push(te);
-            }
-            Err(e) => {
-                return Err(e);
+impl Block {
+    /// The block's text elements, not including those of nested blocks (e.g.
+    /// the blocks in a blockquote) or footnote definitions.
+    fn own_parts(&self) -> Vec<&TextElement> {
+        match self {
+            Block::Paragraph(Paragraph { parts }) | Block::Heading { parts, .. } => {
+                parts.iter().collect()
             }
+            Block::Quote { title, .. } => title.iter().collect(),
+            Block::List { items, .. } => items
+                .iter()
+                .flat_map(|item| {
+                    item.parts
+                        .iter()
+                        .chain(item.children.iter().flat_map(|child| child.own_parts()))
+                })
+                .collect(),
+            Block::Table(table) => table
+                .header
+                .iter()
+                .chain(table.rows.iter().flatten())
+                .flatten()
+                .collect(),
+            Block::DefinitionList(definitions) => definitions
+                .iter()
+                .flat_map(|d| d.term.iter().chain(d.definitions.iter().flatten()))
+                .collect(),
+            Block::Toc
+            | Block::Rule
+            | Block::Transclusion { .. }
+            | Block::FootnoteDef { .. }
+            | Block::LinkDef { .. } => vec![],
         }
     }
-
-    Ok(Paragraph { parts })
 }
 
-// We know that tokens aren't empty
-fn parse_text_element<'a>(
-    tokens: &mut Peekable<Lexer<'a>>,
-) -> Result<TextElement, JustTextError<'a>> {
-    use TokenKind::*;
-
-    let next = tokens.peek().unwrap();
+impl Table {
+    fn resolve(&self, r: &Resolver) -> String {
+        let mut result = String::from("<table><thead>");
+        result.push_str(&self.resolve_row(&self.header, "th", r));
+        result.push_str("</thead><tbody>");
+        for row in &self.rows {
+            result.push_str(&self.resolve_row(row, "td", r));
+        }
+        result.push_str("</tbody></table>");
+        result
+    }
 
-    match next.kind {
-        Text => Ok(parse_text(tokens)),
-        Backtick => parse_mono(tokens),
-        TripleBacktick => parse_block_mono(tokens),
-        LBracket => parse_link(tokens),
-        // Todo: Improve error reporting
-        _ => Err(JustTextError::new("Malformed note input")),
+    fn resolve_row(&self, cells: &[TableCell], tag: &str, r: &Resolver) -> String {
+        let mut result = String::from("<tr>");
+        for (cell, alignment) in cells.iter().zip(&self.alignments) {
+            let style = match alignment {
+                Alignment::Default => "",
+                Alignment::Left => " style=\"text-align: left\"",
+                Alignment::Center => " style=\"text-align: center\"",
+                Alignment::Right => " style=\"text-align: right\"",
+            };
+            // Unlike the rest of a note, text in a cell is escaped
+            let cell = cell
+                .iter()
+                .map(|part| match part {
+                    TextElement::Text(span) => {
+                        escape_html(&resolve_text(r.content, *span, r.emoji, r.typography))
+                    }
+                    _ => part.resolve(r),
+                })
+                .collect::<String>();
+            result.push_str(&format!("<{}{}>{}</{}>", tag, style, cell.trim(), tag));
+        }
+        result.push_str("</tr>");
+        result
     }
 }
 
-fn parse_text<'a>(tokens: &mut Peekable<Lexer<'a>>) -> TextElement {
-    use TokenKind::*;
+fn resolve_list(ordered: bool, items: &[ListItem], r: &Resolver) -> String {
+    let tag = if ordered { "ol" } else { "ul" };
+    let mut result = format!("<{}>", tag);
+    for item in items {
+        match item.done {
+            Some(done) => {
+                let number = r.tasks_resolved.get() + 1;
+                r.tasks_resolved.set(number);
+                result.push_str(&format!(
+                    "<li class=\"task\" id=\"{}task-{}\"><input type=\"checkbox\" disabled{}> ",
+                    r.id_prefix,
+                    number,
+                    if done { " checked" } else { "" }
+                ));
+            }
+            None => result.push_str("<li>"),
+        }
+        result.push_str(resolve_parts(&item.parts, r).trim());
+        for child in &item.children {
+            if let Block::List { ordered, items } = child {
+                result.push_str(&resolve_list(*ordered, items, r));
+            }
+        }
+        result.push_str("</li>");
+    }
+    result.push_str(&format!("</{}>", tag));
+    result
+}
 
-    let (start, mut end) = tokens.next().unwrap().span;
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) => c.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
 
-    while let Some(t) = tokens.peek() {
-        match t.kind {
-            Backtick | TripleBacktick | LBracket | Newline => {
-                break;
-            }
-            _ => {
-                end = t.span.1;
-                tokens.next();
+/// Collects the transclusions in `blocks`, including those in blockquotes.
+fn collect_transclusions(
+    blocks: &[Block],
+    content: &str,
+    transclusions: &mut Vec<(String, usize)>,
+) {
+    for block in blocks {
+        match block {
+            Block::Quote { blocks, .. } => collect_transclusions(blocks, content, transclusions),
+            Block::Transclusion { name } => {
+                let pos = name.0 - "![[".len();
+                transclusions.push((content[name.0..name.1].trim().to_string(), pos));
             }
+            _ => {}
         }
     }
+}
 
-    TextElement::Text((start, end))
+/// Collects the specs of code blocks that include files in `blocks`, including
+/// those in blockquotes.
+fn collect_code_include_specs(blocks: &[Block], content: &str, specs: &mut Vec<(String, usize)>) {
+    for block in blocks {
+        if let Block::Quote { blocks, .. } = block {
+            collect_code_include_specs(blocks, content, specs);
+        }
+        for part in block.own_parts() {
+            if let TextElement::BlockMono {
+                info: Some((s, e)), ..
+            } = part
+            {
+                if let Some(spec) = content[*s..*e].trim().strip_prefix("include ") {
+                    let spec = spec.trim();
+                    specs.push((spec.to_string(), *e - spec.len()));
+                }
+            }
+        }
+    }
 }
 
-fn parse_mono<'a>(tokens: &mut Peekable<Lexer<'a>>) -> Result<TextElement, JustTextError<'a>> {
-    use TokenKind::*;
+/// Collects the terms in `blocks`, including those within blockquotes. Each is
+/// given a unique id derived from the term, prefixed so that it can't clash
+/// with a heading's.
+fn collect_terms(blocks: &[Block], content: &str, terms: &mut Vec<Term>) {
+    let plain_text = |parts: &[TextElement]| {
+        parts
+            .iter()
+            .map(|part| part.plain_text(content))
+            .collect::<String>()
+            .trim()
+            .to_string()
+    };
 
-    let start = tokens.next().unwrap().span.1;
-    let mut end = start;
+    for block in blocks {
+        match block {
+            Block::Quote { blocks, .. } => collect_terms(blocks, content, terms),
+            Block::DefinitionList(definitions) => {
+                for definition in definitions {
+                    let term = plain_text(&definition.term);
 
-    loop {
-        let next = tokens.next();
-        if next.is_none() {
-            return Err(JustTextError::new("Unterminated inline mono"));
-        }
-        let next = next.unwrap();
+                    let slug = format!("term-{}", slugify(&term));
+                    let mut id = slug.clone();
+                    let mut n = 1;
+                    while terms.iter().any(|t| t.id == id) {
+                        n += 1;
+                        id = format!("{}-{}", slug, n);
+                    }
 
-        match next.kind {
-            Backtick => {
-                break;
-            }
-            _ => {
-                end = next.span.1;
+                    terms.push(Term {
+                        id,
+                        term,
+                        definitions: definition
+                            .definitions
+                            .iter()
+                            .map(|d| plain_text(d))
+                            .collect(),
+                    });
+                }
             }
+            _ => {}
         }
     }
+}
 
-    Ok(TextElement::Mono((start, end)))
+/// Collects the tasks in `blocks`, including those in nested lists and
+/// blockquotes.
+fn collect_tasks(blocks: &[Block], content: &str, tasks: &mut Vec<Task>) {
+    for block in blocks {
+        match block {
+            Block::Quote { blocks, .. } => collect_tasks(blocks, content, tasks),
+            Block::List { items, .. } => {
+                for item in items {
+                    if let Some(done) = item.done {
+                        let text = item
+                            .parts
+                            .iter()
+                            .map(|part| part.plain_text(content))
+                            .collect::<String>();
+                        tasks.push(Task {
+                            pos: item.pos,
+                            text: text.trim().to_string(),
+                            done,
+                        });
+                    }
+                    collect_tasks(&item.children, content, tasks);
+                }
+            }
+            _ => {}
+        }
+    }
 }
 
-fn parse_block_mono<'a>(
-    tokens: &mut Peekable<Lexer<'a>>,
-) -> Result<TextElement, JustTextError<'a>> {
-    use TokenKind::*;
+/// Collects the headings in `blocks`, including those within blockquotes. Each
+/// is given an id derived from its title that isn't `taken` or another
+/// heading's. Titles are shown as they're rendered, with emoji and typography
+/// applied if `note` enables them.
+fn collect_headings(
+    blocks: &[Block],
+    content: &str,
+    note: &Note,
+    taken: &[String],
+    headings: &mut Vec<TocHeading>,
+) {
+    for block in blocks {
+        match block {
+            Block::Quote { blocks, .. } => collect_headings(blocks, content, note, taken, headings),
+            Block::Heading { level, parts } => {
+                let plain_title = parts
+                    .iter()
+                    .map(|part| part.plain_text(content))
+                    .collect::<String>();
+                let title = parts
+                    .iter()
+                    .map(|part| part.display_text(content, note.emoji, note.typography))
+                    .collect::<String>()
+                    .trim()
+                    .to_string();
 
-    let start = tokens.next().unwrap().span.1;
-    let mut end = start;
+                let slug = slugify(&plain_title);
+                let mut id = slug.clone();
+                let mut n = 1;
+                while taken.contains(&id) || headings.iter().any(|h| h.id == id) {
+                    n += 1;
+                    id = format!("{}-{}", slug, n);
+                }
 
-    loop {
-        let next = tokens.next();
-        if next.is_none() {
-            return Err(JustTextError::new("Unterminated block mono"));
+                headings.push(TocHeading {
+                    level: *level,
+                    id,
+                    title,
+                });
+            }
+            _ => {}
         }
-        let next = next.unwrap();
+    }
+}
 
-        match next.kind {
-            TripleBacktick => {
-                break;
+/// Collects the destination and position of each link in `blocks`, including
+/// those within blockquotes and footnotes.
+fn collect_links(blocks: &[Block], content: &str, links: &mut Vec<(String, usize)>) {
+    for block in blocks {
+        let mut parts = block.own_parts();
+        match block {
+            Block::Quote { blocks, .. } => collect_links(blocks, content, links),
+            Block::FootnoteDef {
+                parts: definition, ..
+            } => parts.extend(definition),
+            _ => {}
+        }
+
+        for part in parts.into_iter().flat_map(|part| part.descendants()) {
+            if let TextElement::Link { href, .. } = part {
+                links.push((unescape(&content[href.0..href.1]), href.0));
             }
-            _ => {
-                end = next.span.1;
+        }
+    }
+}
+
+impl Paragraph {
+    fn resolve(&self, r: &Resolver) -> String {
+        // Code blocks can't be nested in a <p>, so they split the paragraph
+        let mut result = String::new();
+        let mut inline = String::new();
+        for part in &self.parts {
+            if part.is_block() {
+                push_inline(&mut result, &mut inline);
+                result.push_str(&part.resolve(r));
+            } else {
+                inline.push_str(&part.resolve(r));
             }
         }
+        push_inline(&mut result, &mut inline);
+        result
     }
+}
 
-    Ok(TextElement::BlockMono((start, end)))
+fn push_inline(result: &mut String, inline: &mut String) {
+    let trimmed = inline.trim();
+    if !trimmed.is_empty() {
+        result.push_str(&format!("<p>{}</p>", trimmed));
+    }
+    inline.clear();
 }
 
-fn parse_link<'a>(tokens: &mut Peekable<Lexer<'a>>) -> Result<TextElement, JustTextError<'a>> {
-    use TokenKind::*;
+/// Removes `depth` quote markers (">", and a space if there is one) from the
+/// start of each line of `code`.
+fn unquote(code: &str, depth: usize) -> String {
+    code.split('\n')
+        .map(|line| {
+            let mut line = line;
+            for _ in 0..depth {
+                line = line
+                    .strip_prefix("> ")
+                    .or_else(|| line.strip_prefix('>'))
+                    .unwrap_or(line);
+            }
+            line
+        })
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
 
-    // Consume "["
-    tokens.next();
+/// Removes up to `indent` leading whitespace chars from each line of `code`.
+fn dedent(code: &str, indent: usize) -> String {
+    code.split('\n')
+        .map(|line| {
+            let len = line
+                .chars()
+                .take(indent)
+                .take_while(|c| *c == ' ' || *c == '\t')
+                .count();
+            // Spaces and tabs are a single byte each
+            &line[len..]
+        })
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
 
-    // title
-    let next = tokens.next();
-    if next.is_none() {
-        return Err(JustTextError::new("Incomplete link"));
+/// Renders text, replacing emoji shortcodes and applying typography if enabled.
+fn resolve_text(content: &str, (s, e): Span, emoji: bool, typography: bool) -> String {
+    let before = content[..s].chars().next_back();
+    let text = if emoji {
+        emojify(&content[s..e], before)
+    } else {
+        content[s..e].to_string()
+    };
+    if typography {
+        // Whether a quote opens or closes depends on what's on the other side
+        // of any emphasis, code, or math delimiters, as in *"this"*
+        let before = content[..s]
+            .trim_end_matches(&['*', '`', '$'][..])
+            .chars()
+            .next_back();
+        smarten(&text, before)
+    } else {
+        unescape(&text)
     }
-    let next = next.unwrap();
-    if next.kind != Text {
-        return Err(JustTextError::new(
-            "Invalid link: expected text after \"[\"",
-        ));
+}
+
+fn resolve_parts(parts: &[TextElement], r: &Resolver) -> String {
+    parts.iter().map(|part| part.resolve(r)).collect()
+}
+
+impl TextElement {
+    fn resolve(&self, r: &Resolver) -> String {
+        let content = r.content;
+        match self {
+            TextElement::Text(span) => resolve_text(content, *span, r.emoji, r.typography),
+            TextElement::Link {
+                title,
+                href,
+                tooltip,
+            } => {
+                let mut href = unescape(&content[href.0..href.1]);
+                // Links within the note lead to its own (prefixed) ids
+                if href.starts_with('#') {
+                    href.insert_str(1, r.id_prefix);
+                }
+                let tooltip = match tooltip {
+                    Some((s, e)) => {
+                        format!(" title=\"{}\"", escape_html(&unescape(&content[*s..*e])))
+                    }
+                    None => String::new(),
+                };
+                format!(
+                    "<a href=\"{}\"{}>{}</a>",
+                    escape_html(&href),
+                    tooltip,
+                    resolve_parts(title, r)
+                )
+            }
+            // Reference links are replaced with links after parsing
+            TextElement::RefLink { title, .. } => resolve_parts(title, r),
+            TextElement::Emphasis { strong, parts } => {
+                let tag = if *strong { "strong" } else { "em" };
+                format!("<{}>{}</{}>", tag, resolve_parts(parts, r), tag)
+            }
+            TextElement::Mono((s, e)) => {
+                let slice = &content[*s..*e].trim();
+                format!("<span class=\"mono\">{}</span>", escape_html(slice))
+            }
+            TextElement::FootnoteRef {
+                span,
+                number,
+                occurrence,
+            } => match r.footnotes[number - 1] {
+                Some(_) => format!(
+                    "<sup class=\"footnote-ref\"><a id=\"{}{}\" href=\"#{}fn-{}\">{}</a></sup>",
+                    r.id_prefix,
+                    footnote_ref_id(*number, *occurrence),
+                    r.id_prefix,
+                    number,
+                    number
+                ),
+                // References to footnotes that aren't defined are just text
+                None => content[span.0..span.1].to_string(),
+            },
+            TextElement::Math { mathml, .. } => mathml.clone(),
+            TextElement::Autolink((s, e)) => {
+                let text = &content[*s..*e];
+                let href = if text
+                    .get(..4)
+                    .is_some_and(|p| p.eq_ignore_ascii_case("www."))
+                {
+                    format!("https://{}", text)
+                } else if !text.contains("://") {
+                    format!("mailto:{}", text)
+                } else {
+                    text.to_string()
+                };
+                format!(
+                    "<a href=\"{}\">{}</a>",
+                    escape_html(&href),
+                    escape_html(text)
+                )
+            }
+            TextElement::LineBreak { span, hard } => {
+                // Two or more trailing spaces also make for a hard break
+                if *hard || content[..span.0].ends_with("  ") {
+                    String::from("<br>\n")
+                } else {
+                    String::from("\n")
+                }
+            }
+            TextElement::BlockMono {
+                info,
+                code,
+                indent,
+                quote_depth,
+            } => {
+                let info = info.map(|(s, e)| content[s..e].trim());
+                // The code in an "include" block comes from the file it names
+                let (code, language) = match info.and_then(|info| info.strip_prefix("include ")) {
+                    Some(spec) => {
+                        let spec = spec.trim();
+                        let code = r.code_includes.iter().find(|(s, _)| s == spec);
+                        let path = spec.split('#').next().unwrap_or(spec);
+                        (
+                            code.map(|(_, code)| code.clone()).unwrap_or_default(),
+                            language(path),
+                        )
+                    }
+                    None => (
+                        dedent(&unquote(&content[code.0..code.1], *quote_depth), *indent),
+                        info.and_then(|info| info.split_whitespace().next())
+                            .map(String::from),
+                    ),
+                };
+                let code = code.as_str();
+
+                match language.as_deref() {
+                    Some(language) => format!(
+                        "<pre><code class=\"language-{}\">{}</code></pre>",
+                        escape_html(language),
+                        highlight(language, code).unwrap_or_else(|| escape_html(code))
+                    ),
+                    None => format!("<pre><code>{}</code></pre>", escape_html(code)),
+                }
+            }
+        }
     }
-    let title_span = next.span;
 
-    // ]
-    let next = tokens.next();
-    if next.is_none() {
-        return Err(JustTextError::new("Incomplete link"));
+    fn is_block(&self) -> bool {
+        matches!(self, TextElement::BlockMono { .. })
     }
-    let next = next.unwrap();
-    if next.kind != RBracket {
-        return Err(JustTextError::new(
-            "Invalid link: expected \"]\" after href",
-        ));
+
+    /// The elements nested in this one, e.g. the parts of a link's title.
+    fn children(&self) -> &[TextElement] {
+        match self {
+            TextElement::Link { title, .. } | TextElement::RefLink { title, .. } => title,
+            TextElement::Emphasis { parts, .. } => parts,
+            _ => &[],
+        }
     }
 
-    // (
-    let next = tokens.next();
-    if next.is_none() {
-        return Err(JustTextError::new("Incomplete link"));
+    fn children_mut(&mut self) -> &mut [TextElement] {
+        match self {
+            TextElement::Link { title, .. } | TextElement::RefLink { title, .. } => title,
+            TextElement::Emphasis { parts, .. } => parts,
+            _ => &mut [],
+        }
     }
-    let next = next.unwrap();
-    if next.kind != LParen {
-        return Err(JustTextError::new(
-            "Invalid link: expected \"(\" after \"]\"",
-        ));
+
+    /// This element followed by all of the elements nested in it, in order.
+    fn descendants(&self) -> Vec<&TextElement> {
+        let mut result = vec![self];
+        for child in self.children() {
+            result.extend(child.descendants());
+        }
+        result
     }
 
-    // href
-    let next = tokens.next();
-    if next.is_none() {
-        return Err(JustTextError::new("Incomplete link"));
+    /// The element's text, without any markup.
+    fn plain_text(&self, content: &str) -> String {
+        match self {
+            TextElement::Text((s, e)) => unescape(&content[*s..*e]),
+            TextElement::BlockMono { code, .. } => content[code.0..code.1].to_string(),
+            TextElement::Link { title, .. }
+            | TextElement::RefLink { title, .. }
+            | TextElement::Emphasis { parts: title, .. } => {
+                title.iter().map(|part| part.plain_text(content)).collect()
+            }
+            TextElement::Mono((s, e)) => content[*s..*e].trim().to_string(),
+            TextElement::LineBreak { .. } => String::from(" "),
+            TextElement::FootnoteRef { .. } => String::new(),
+            TextElement::Math { tex, .. } => content[tex.0..tex.1].to_string(),
+            TextElement::Autolink((s, e)) => content[*s..*e].to_string(),
+        }
     }
-    let next = next.unwrap();
-    if next.kind != Text {
-        return Err(JustTextError::new(
-            "Invalid link: expected text after \"(\"",
-        ));
+
+    /// Like `plain_text`, but with emoji and typography applied as they are
+    /// when the element is rendered.
+    fn display_text(&self, content: &str, emoji: bool, typography: bool) -> String {
+        match self {
+            TextElement::Text(span) => resolve_text(content, *span, emoji, typography),
+            TextElement::Link { title, .. }
+            | TextElement::RefLink { title, .. }
+            | TextElement::Emphasis { parts: title, .. } => title
+                .iter()
+                .map(|part| part.display_text(content, emoji, typography))
+                .collect(),
+            _ => self.plain_text(content),
+        }
     }
-    let href_span = next.span;
+}
 
-    // )
-    let next = tokens.next();
-    if next.is_none() {
-        return Err(JustTextError::new("Incomplete link"));
+pub fn parse(input: &str, options: Options) -> Result<Note, JustTextError<'static>> {
+    let mut ctx = Context {
+        content: input,
+        options,
+        warnings: vec![],
+        quote_depth: 0,
+        footnotes: vec![],
+    };
+    let tokens = Lexer::new(input).collect::<Vec<Token>>();
+    let mut note = parse_note(&mut Tokens::new(&tokens), &mut ctx)?;
+    check_footnotes(&note.blocks, &mut ctx)?;
+    resolve_link_refs(&mut note.blocks, &mut ctx)?;
+    if options.emoji {
+        check_shortcodes(&mut note.blocks, &mut ctx)?;
     }
-    let next = next.unwrap();
-    if next.kind != RParen {
-        return Err(JustTextError::new(
-            "Invalid link: expected \")\" after title",
+    note.footnotes = ctx.footnotes;
+    note.sidenotes = options.sidenotes;
+    note.typography = options.typography;
+    note.emoji = options.emoji;
+    note.word_count = count_words(input, &tokens);
+    note.warnings = ctx.warnings;
+
+    Ok(note)
+}
+
+/// Counts the words in `input` that are part of a token, which leaves out
+/// comments.
+fn count_words(input: &str, tokens: &[Token]) -> usize {
+    let mut text = String::new();
+    let mut end = 0;
+    for token in tokens {
+        if token.span.0 > end {
+            text.push('\n');
+        }
+        text.push_str(&input[token.span.0..token.span.1]);
+        end = token.span.1;
+    }
+
+    text.split_whitespace().count()
+}
+
+fn parse_note(tokens: &mut Tokens, ctx: &mut Context) -> Result<Note, JustTextError<'static>> {
+    Ok(Note {
+        blocks: parse_blocks(tokens, ctx)?,
+        ..Note::default()
+    })
+}
+
+/// Calls `f` on each text element in `blocks`, including those in nested
+/// blocks and nested in other elements.
+fn visit_parts_mut<F>(blocks: &mut [Block], f: &mut F)
+where
+    F: FnMut(&mut TextElement),
+{
+    for block in blocks {
+        match block {
+            Block::Paragraph(Paragraph { parts })
+            | Block::Heading { parts, .. }
+            | Block::FootnoteDef { parts, .. } => visit_elements_mut(parts, f),
+            Block::Quote { title, blocks, .. } => {
+                visit_elements_mut(title, f);
+                visit_parts_mut(blocks, f);
+            }
+            Block::List { items, .. } => {
+                for item in items {
+                    visit_elements_mut(&mut item.parts, f);
+                    visit_parts_mut(&mut item.children, f);
+                }
+            }
+            Block::Table(table) => {
+                for cell in table
+                    .header
+                    .iter_mut()
+                    .chain(table.rows.iter_mut().flatten())
+                {
+                    visit_elements_mut(cell, f);
+                }
+            }
+            Block::DefinitionList(definitions) => {
+                for definition in definitions {
+                    visit_elements_mut(&mut definition.term, f);
+                    for parts in &mut definition.definitions {
+                        visit_elements_mut(parts, f);
+                    }
+                }
+            }
+            Block::Toc | Block::Rule | Block::Transclusion { .. } | Block::LinkDef { .. } => {}
+        }
+    }
+}
+
+fn visit_elements_mut<F>(parts: &mut [TextElement], f: &mut F)
+where
+    F: FnMut(&mut TextElement),
+{
+    for part in parts {
+        f(part);
+        visit_elements_mut(part.children_mut(), f);
+    }
+}
+
+/// Link labels match regardless of case and spacing.
+fn normalize_label(label: &str) -> String {
+    label
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Collects each link definition's (normalized) label, and its label and
+/// href spans.
+fn collect_link_defs(blocks: &[Block], content: &str, definitions: &mut Vec<(String, Span, Span)>) {
+    for block in blocks {
+        match block {
+            Block::Quote { blocks, .. } => collect_link_defs(blocks, content, definitions),
+            Block::LinkDef { label, href } => {
+                let normalized = normalize_label(&content[label.0..label.1]);
+                definitions.push((normalized, *label, *href));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Replaces each reference link with a link to the URL defined for it.
+/// References that aren't defined are rendered as text, and they and
+/// definitions that are duplicated or never used are reported as warnings.
+fn resolve_link_refs(
+    blocks: &mut [Block],
+    ctx: &mut Context,
+) -> Result<(), JustTextError<'static>> {
+    let content = ctx.content;
+    let mut definitions = vec![];
+    collect_link_defs(blocks, content, &mut definitions);
+
+    let mut used = vec![false; definitions.len()];
+    let mut undefined = vec![];
+    visit_parts_mut(blocks, &mut |part| {
+        if let TextElement::RefLink { title, label, span } = part {
+            let normalized = normalize_label(&content[label.0..label.1]);
+            match definitions.iter().position(|(l, ..)| *l == normalized) {
+                Some(i) => {
+                    used[i] = true;
+                    *part = TextElement::Link {
+                        title: std::mem::take(title),
+                        href: definitions[i].2,
+                        tooltip: None,
+                    };
+                }
+                None => {
+                    undefined.push(*label);
+                    *part = TextElement::Text(*span);
+                }
+            }
+        }
+    });
+
+    for label in undefined {
+        let message = format!(
+            "Link reference \"{}\" isn't defined",
+            &content[label.0..label.1]
+        );
+        if ctx.options.strict {
+            return Err(JustTextError::at(message, label.0));
+        }
+        ctx.warnings.push(Warning {
+            message,
+            pos: label.0,
+        });
+    }
+
+    for (i, (normalized, label, _)) in definitions.iter().enumerate() {
+        let message = if definitions[..i]
+            .iter()
+            .any(|(other, ..)| other == normalized)
+        {
+            format!(
+                "Link reference \"{}\" is defined more than once",
+                &content[label.0..label.1]
+            )
+        } else if !used[i] {
+            format!(
+                "Link reference \"{}\" is never used",
+                &content[label.0..label.1]
+            )
+        } else {
+            continue;
+        };
+        ctx.warnings.push(Warning {
+            message,
+            pos: label.0,
+        });
+    }
+
+    Ok(())
+}
+
+/// Reports emoji shortcodes that aren't known, which are rendered as text.
+fn check_shortcodes(blocks: &mut [Block], ctx: &mut Context) -> Result<(), JustTextError<'static>> {
+    let content = ctx.content;
+    let mut unknown = vec![];
+    visit_parts_mut(blocks, &mut |part| {
+        if let TextElement::Text((s, e)) = part {
+            let before = content[..*s].chars().next_back();
+            for (start, end) in shortcodes(&content[*s..*e], before) {
+                if lookup(&content[*s + start + 1..*s + end - 1]).is_none() {
+                    unknown.push((*s + start, *s + end));
+                }
+            }
+        }
+    });
+
+    for (start, end) in unknown {
+        let message = format!("Unknown emoji shortcode \"{}\"", &content[start..end]);
+        if ctx.options.strict {
+            return Err(JustTextError::at(message, start));
+        }
+        ctx.warnings.push(Warning {
+            message,
+            pos: start,
+        });
+    }
+
+    Ok(())
+}
+
+/// Reports references to footnotes that aren't defined (which are rendered as
+/// text), and definitions that are duplicated or never referenced (which
+/// aren't rendered).
+fn check_footnotes(blocks: &[Block], ctx: &mut Context) -> Result<(), JustTextError<'static>> {
+    let mut definitions = vec![];
+    collect_footnote_defs(blocks, ctx.content, &mut definitions);
+
+    for footnote in &ctx.footnotes {
+        if !definitions
+            .iter()
+            .any(|(label, ..)| *label == footnote.label)
+        {
+            let message = format!("Footnote \"{}\" isn't defined", footnote.label);
+            if ctx.options.strict {
+                return Err(JustTextError::at(message, footnote.pos));
+            }
+            ctx.warnings.push(Warning {
+                message,
+                pos: footnote.pos,
+            });
+        }
+    }
+
+    for (i, (label, pos, _)) in definitions.iter().enumerate() {
+        let message = if definitions[..i].iter().any(|(other, ..)| other == label) {
+            format!("Footnote \"{}\" is defined more than once", label)
+        } else if !ctx.footnotes.iter().any(|f| f.label == *label) {
+            format!("Footnote \"{}\" is never referenced", label)
+        } else {
+            continue;
+        };
+        ctx.warnings.push(Warning { message, pos: *pos });
+    }
+
+    Ok(())
+}
+
+fn parse_blocks(
+    tokens: &mut Tokens,
+    ctx: &mut Context,
+) -> Result<Vec<Block>, JustTextError<'static>> {
+    let mut blocks = Vec::new();
+
+    while let Some(t) = tokens.peek() {
+        if t.kind == TokenKind::Newline {
+            tokens.next();
+            continue;
+        }
+
+        blocks.push(parse_block(tokens, ctx)?);
+    }
+
+    Ok(blocks)
+}
+
+// We know that tokens aren't empty
+fn parse_block(tokens: &mut Tokens, ctx: &mut Context) -> Result<Block, JustTextError<'static>> {
+    use TokenKind::*;
+
+    match tokens.peek().unwrap().kind {
+        Heading(_) => parse_heading(tokens, ctx),
+        TocMarker => {
+            tokens.next();
+            Ok(Block::Toc)
+        }
+        Rule => {
+            tokens.next();
+            Ok(Block::Rule)
+        }
+        Transclusion => {
+            let span = tokens.next().unwrap().span;
+            let line = ctx.content[span.0..span.1].trim_end();
+            Ok(Block::Transclusion {
+                name: (span.0 + "![[".len(), span.0 + line.len() - "]]".len()),
+            })
+        }
+        QuoteMarker => parse_quote(tokens, ctx),
+        TableRow if separator_ahead(tokens, ctx) => parse_table(tokens, ctx),
+        Bullet(_) | Ordinal(_) => parse_list(tokens, ctx),
+        LinkDef => parse_link_def(tokens, ctx),
+        FootnoteDef => {
+            let marker = tokens.next().unwrap().span;
+            let label = (
+                marker.0 + 2,
+                marker.0 + ctx.content[marker.0..].find(']').unwrap(),
+            );
+            let Paragraph { parts } = parse_paragraph(tokens, ctx)?;
+            Ok(Block::FootnoteDef { label, parts })
+        }
+        _ if definition_ahead(tokens) => parse_definition_list(tokens, ctx),
+        _ => parse_paragraph(tokens, ctx).map(Block::Paragraph),
+    }
+}
+
+/// Whether the tokens begin with a line that's followed by a definition.
+fn definition_ahead(tokens: &Tokens) -> bool {
+    let mut tokens = tokens.clone();
+    while let Some(t) = tokens.next() {
+        if let TokenKind::Newline | TokenKind::HardBreak = t.kind {
+            return tokens.peek().map(|t| t.kind) == Some(TokenKind::DefinitionMarker);
+        }
+    }
+
+    false
+}
+
+/// Parses terms, each followed by one or more definitions. Blank lines between
+/// them don't end the list.
+fn parse_definition_list(
+    tokens: &mut Tokens,
+    ctx: &mut Context,
+) -> Result<Block, JustTextError<'static>> {
+    let mut definitions = vec![];
+
+    loop {
+        let term = parse_line(tokens, ctx)?;
+        tokens.next();
+
+        let mut item = Definition {
+            term,
+            definitions: vec![],
+        };
+        while tokens.peek().map(|t| t.kind) == Some(TokenKind::DefinitionMarker) {
+            tokens.next();
+            item.definitions.push(parse_line(tokens, ctx)?);
+            tokens.next();
+        }
+        definitions.push(item);
+
+        let mut ahead = tokens.clone();
+        while ahead.peek().map(|t| t.kind) == Some(TokenKind::Newline) {
+            ahead.next();
+        }
+        if ahead.peek().is_none_or(|t| starts_block(&t.kind)) || !definition_ahead(&ahead) {
+            break;
+        }
+        *tokens = ahead;
+    }
+
+    Ok(Block::DefinitionList(definitions))
+}
+
+/// Parses text elements up to (but not including) the end of the line.
+fn parse_line(
+    tokens: &mut Tokens,
+    ctx: &mut Context,
+) -> Result<Vec<TextElement>, JustTextError<'static>> {
+    let mut parts = Vec::new();
+
+    while let Some(t) = tokens.peek() {
+        if let TokenKind::Newline | TokenKind::HardBreak = t.kind {
+            break;
+        }
+        parts.push(parse_text_element(tokens, ctx)?);
+    }
+
+    Ok(parts)
+}
+
+fn parse_heading(tokens: &mut Tokens, ctx: &mut Context) -> Result<Block, JustTextError<'static>> {
+    let level = match tokens.next().unwrap().kind {
+        TokenKind::Heading(level) => level,
+        _ => unreachable!(),
+    };
+    let mut parts = Vec::new();
+
+    while let Some(t) = tokens.peek() {
+        match t.kind {
+            TokenKind::Newline => break,
+            TokenKind::HardBreak => {
+                tokens.next();
+                break;
+            }
+            _ => parts.push(parse_text_element(tokens, ctx)?),
+        }
+    }
+
+    Ok(Block::Heading { level, parts })
+}
+
+/// Parses consecutive quoted lines. With the first marker on each line
+/// removed, those lines are parsed as blocks of their own.
+fn parse_quote(tokens: &mut Tokens, ctx: &mut Context) -> Result<Block, JustTextError<'static>> {
+    let mut quoted = vec![];
+
+    while let Some(t) = tokens.peek() {
+        if t.kind != TokenKind::QuoteMarker {
+            break;
+        }
+        tokens.next();
+
+        for t in tokens.by_ref() {
+            let line_end = matches!(t.kind, TokenKind::Newline | TokenKind::HardBreak);
+            quoted.push(t);
+            if line_end {
+                break;
+            }
+        }
+    }
+
+    let mut quoted = Tokens::new(&quoted);
+    let mut callout = None;
+    let mut title = vec![];
+
+    if let Some(t) = quoted.peek() {
+        if t.kind == TokenKind::CalloutMarker {
+            // Just the label, without "[!" and "]"
+            callout = Some((t.span.0 + 2, t.span.1 - 1));
+            quoted.next();
+
+            while let Some(t) = quoted.peek() {
+                if let TokenKind::Newline | TokenKind::HardBreak = t.kind {
+                    break;
+                }
+                title.push(parse_text_element(&mut quoted, ctx)?);
+            }
+        }
+    }
+
+    ctx.quote_depth += 1;
+    let blocks = parse_blocks(&mut quoted, ctx);
+    ctx.quote_depth -= 1;
+
+    Ok(Block::Quote {
+        callout,
+        title,
+        blocks: blocks?,
+    })
+}
+
+/// Whether the token is a list marker, and if so, whether the list is ordered
+/// and how far the marker is indented.
+fn list_marker(kind: TokenKind) -> Option<(bool, usize)> {
+    match kind {
+        TokenKind::Bullet(indent) => Some((false, indent)),
+        TokenKind::Ordinal(indent) => Some((true, indent)),
+        _ => None,
+    }
+}
+
+/// Parses items of the same kind and indentation, along with any lists nested
+/// beneath them. Blank lines between items don't end the list.
+fn parse_list(tokens: &mut Tokens, ctx: &mut Context) -> Result<Block, JustTextError<'static>> {
+    let marker = list_marker(tokens.peek().unwrap().kind);
+    let (ordered, indent) = marker.unwrap();
+    let mut items = vec![];
+
+    loop {
+        let mut ahead = tokens.clone();
+        while ahead.peek().map(|t| t.kind) == Some(TokenKind::Newline) {
+            ahead.next();
+        }
+        if ahead.peek().and_then(|t| list_marker(t.kind)) != marker {
+            break;
+        }
+        let pos = ahead.next().unwrap().span.0;
+        *tokens = ahead;
+
+        items.push(parse_list_item(tokens, ctx, indent, pos)?);
+    }
+
+    Ok(Block::List { ordered, items })
+}
+
+fn parse_list_item(
+    tokens: &mut Tokens,
+    ctx: &mut Context,
+    indent: usize,
+    pos: usize,
+) -> Result<ListItem, JustTextError<'static>> {
+    let done = match tokens.peek().map(|t| t.kind) {
+        Some(TokenKind::Checkbox(checked)) => {
+            tokens.next();
+            Some(checked)
+        }
+        _ => None,
+    };
+    let mut parts = vec![];
+
+    while let Some(t) = tokens.peek() {
+        if let TokenKind::Newline | TokenKind::HardBreak = t.kind {
+            let line_end = tokens.next().unwrap();
+
+            // Lines that don't start a new block continue the item
+            match tokens.peek() {
+                Some(t) if t.kind != TokenKind::Newline && !starts_block(&t.kind) => {
+                    parts.push(TextElement::LineBreak {
+                        span: line_end.span,
+                        hard: line_end.kind == TokenKind::HardBreak || ctx.options.preserve_lines,
+                    });
+                }
+                _ => break,
+            }
+            continue;
+        }
+
+        parts.push(parse_text_element(tokens, ctx)?);
+    }
+
+    let mut children = vec![];
+    while let Some((_, child_indent)) = tokens.peek().and_then(|t| list_marker(t.kind)) {
+        if child_indent <= indent {
+            break;
+        }
+        children.push(parse_list(tokens, ctx)?);
+    }
+
+    Ok(ListItem {
+        done,
+        pos,
+        parts,
+        children,
+    })
+}
+
+/// Parses a header row, a separator row, and any further rows, each of which
+/// must have as many cells as the header.
+fn parse_table(tokens: &mut Tokens, ctx: &mut Context) -> Result<Block, JustTextError<'static>> {
+    let header = parse_table_row(tokens, ctx)?;
+
+    let separator_pos = tokens.peek().unwrap().span.0;
+    let alignments = parse_table_separator(tokens, ctx).unwrap();
+    if alignments.len() != header.len() {
+        return Err(ragged_row_error(
+            alignments.len(),
+            header.len(),
+            separator_pos,
+        ));
+    }
+
+    let mut rows = vec![];
+    while let Some(t) = tokens.peek() {
+        if t.kind != TokenKind::TableRow {
+            break;
+        }
+        let row_pos = t.span.0;
+        let row = parse_table_row(tokens, ctx)?;
+        if row.len() != header.len() {
+            return Err(ragged_row_error(row.len(), header.len(), row_pos));
+        }
+        rows.push(row);
+    }
+
+    Ok(Block::Table(Table {
+        alignments,
+        header,
+        rows,
+    }))
+}
+
+fn ragged_row_error(cells: usize, expected: usize, pos: usize) -> JustTextError<'static> {
+    JustTextError::at(
+        format!(
+            "Table row has {} cell(s), but the table's header has {}",
+            cells, expected
+        ),
+        pos,
+    )
+}
+
+/// Parses a row's cells, through the end of its line. The "|" after the last
+/// cell is optional.
+fn parse_table_row(
+    tokens: &mut Tokens,
+    ctx: &mut Context,
+) -> Result<Vec<TableCell>, JustTextError<'static>> {
+    // Consume the leading "|"
+    tokens.next();
+
+    let mut cells = vec![];
+    let mut cell = vec![];
+    while let Some(t) = tokens.peek() {
+        match t.kind {
+            TokenKind::Newline | TokenKind::HardBreak => {
+                tokens.next();
+                break;
+            }
+            TokenKind::Pipe => {
+                tokens.next();
+                cells.push(std::mem::take(&mut cell));
+            }
+            _ => cell.push(parse_text_element(tokens, ctx)?),
+        }
+    }
+
+    let content = ctx.content;
+    if cell
+        .iter()
+        .any(|part| !part.plain_text(content).trim().is_empty())
+    {
+        cells.push(cell);
+    }
+
+    Ok(cells)
+}
+
+/// Parses a row like "| :--- | :---: |" into each column's alignment, or
+/// returns `None` if the row isn't a separator row.
+fn parse_table_separator(tokens: &mut Tokens, ctx: &Context) -> Option<Vec<Alignment>> {
+    if tokens.peek()?.kind != TokenKind::TableRow {
+        return None;
+    }
+    let start = tokens.next().unwrap().span.1;
+    let mut end = start;
+    for t in tokens.by_ref() {
+        if let TokenKind::Newline | TokenKind::HardBreak = t.kind {
+            break;
+        }
+        end = t.span.1;
+    }
+
+    let row = ctx.content[start..end].trim();
+    let row = row.strip_suffix('|').unwrap_or(row);
+    row.split('|')
+        .map(|cell| {
+            let cell = cell.trim();
+            let left = cell.starts_with(':');
+            let right = cell.len() > 1 && cell.ends_with(':');
+            let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+            if dashes.is_empty() || dashes.chars().any(|c| c != '-') {
+                return None;
+            }
+
+            Some(match (left, right) {
+                (true, true) => Alignment::Center,
+                (true, false) => Alignment::Left,
+                (false, true) => Alignment::Right,
+                (false, false) => Alignment::Default,
+            })
+        })
+        .collect()
+}
+
+/// Whether the line after this one is a table's separator row, i.e. whether
+/// this line is a table's header. Other lines beginning with "|" are text.
+fn separator_ahead(tokens: &Tokens, ctx: &Context) -> bool {
+    let mut tokens = tokens.clone();
+    for t in tokens.by_ref() {
+        if let TokenKind::Newline | TokenKind::HardBreak = t.kind {
+            break;
+        }
+    }
+    parse_table_separator(&mut tokens, ctx).is_some()
+}
+
+/// Whether a line beginning with this token starts a new block.
+fn starts_block(kind: &TokenKind) -> bool {
+    use TokenKind::*;
+
+    matches!(
+        kind,
+        Heading(_)
+            | TocMarker
+            | Rule
+            | Transclusion
+            | Fence(_)
+            | QuoteMarker
+            | Bullet(_)
+            | Ordinal(_)
+            | TableRow
+            | FootnoteDef
+            | LinkDef
+    )
+}
+
+fn parse_paragraph(
+    tokens: &mut Tokens,
+    ctx: &mut Context,
+) -> Result<Paragraph, JustTextError<'static>> {
+    let mut parts = Vec::new();
+
+    while let Some(t) = tokens.peek() {
+        if let TokenKind::Newline | TokenKind::HardBreak = t.kind {
+            let line_end = tokens.next().unwrap();
+
+            // A blank line (or the start of another block) ends the paragraph
+            match tokens.peek() {
+                None => break,
+                Some(t) if t.kind == TokenKind::Newline => {
+                    tokens.next();
+                    break;
+                }
+                Some(t) if starts_block(&t.kind) => break,
+                _ => {
+                    parts.push(TextElement::LineBreak {
+                        span: line_end.span,
+                        hard: line_end.kind == TokenKind::HardBreak || ctx.options.preserve_lines,
+                    });
+                }
+            }
+            continue;
+        }
+
+        parts.push(parse_text_element(tokens, ctx)?);
+    }
+
+    Ok(Paragraph { parts })
+}
+
+// We know that tokens aren't empty
+fn parse_text_element(
+    tokens: &mut Tokens,
+    ctx: &mut Context,
+) -> Result<TextElement, JustTextError<'static>> {
+    use TokenKind::*;
+
+    let next = tokens.peek().unwrap().kind;
+
+    match next {
+        Backtick => parse_or_fall_back(tokens, ctx, |tokens, _| parse_mono(tokens)),
+        Fence(_) => parse_or_fall_back(tokens, ctx, |tokens, ctx| {
+            parse_block_mono(tokens, ctx.quote_depth)
+        }),
+        LBracket if link_ahead(tokens) => parse_or_fall_back(tokens, ctx, parse_link),
+        LBracket if ref_link_ahead(tokens) => parse_or_fall_back(tokens, ctx, parse_ref_link),
+        Star { opens: true, .. } if emphasis_ahead(tokens) => {
+            parse_or_fall_back(tokens, ctx, parse_emphasis)
+        }
+        FootnoteRef => Ok(parse_footnote_ref(tokens, ctx)),
+        Autolink if ctx.options.autolink => Ok(TextElement::Autolink(tokens.next().unwrap().span)),
+        Dollar { opens: true, .. } if math_ahead(tokens) => {
+            parse_or_fall_back(tokens, ctx, |tokens, ctx| parse_math(tokens, ctx.content))
+        }
+        DoubleDollar => {
+            parse_or_fall_back(tokens, ctx, |tokens, ctx| parse_math(tokens, ctx.content))
+        }
+        // Brackets and parens that aren't part of a link are just text, as are
+        // a callout marker outside of a blockquote, ": " without a term above
+        // it, "|" outside of a table,
+        // "$" that doesn't start math, "*" that doesn't start emphasis (and
+        // URLs, if they aren't being linked)
+        Text
+        | Escape
+        | LBracket
+        | RBracket
+        | LParen
+        | RParen
+        | CalloutMarker
+        | TableRow
+        | Pipe
+        | Dollar { .. }
+        | Star { .. }
+        | DefinitionMarker
+        | Autolink => Ok(parse_text(tokens)),
+        // Todo: Improve error reporting
+        _ => Err(JustTextError::new("Malformed note input")),
+    }
+}
+
+/// Unless parsing strictly, markup that can't be parsed by `parse` is rendered
+/// as text, up to the next (potential) markup.
+fn parse_or_fall_back<F>(
+    tokens: &mut Tokens,
+    ctx: &mut Context,
+    parse: F,
+) -> Result<TextElement, JustTextError<'static>>
+where
+    F: FnOnce(&mut Tokens, &mut Context) -> Result<TextElement, JustTextError<'static>>,
+{
+    let checkpoint = tokens.clone();
+    // Markup nested in what couldn't be parsed doesn't count either
+    let warnings = ctx.warnings.len();
+    let footnotes = ctx.footnotes.clone();
+
+    match parse(tokens, ctx) {
+        Err(e) if !ctx.options.strict => {
+            *tokens = checkpoint;
+            ctx.warnings.truncate(warnings);
+            ctx.footnotes = footnotes;
+            ctx.warnings.push(Warning {
+                message: e.to_string(),
+                pos: e.pos.unwrap_or(tokens.peek().unwrap().span.0),
+            });
+            Ok(parse_text(tokens))
+        }
+        result => result,
+    }
+}
+
+/// The tokens following a link's title, if the tokens begin with one, i.e. a
+/// "[" that's followed by something other than "]" and matched by a "]" on
+/// the same line.
+fn after_link_title<'t>(tokens: &Tokens<'t>) -> Option<Tokens<'t>> {
+    use TokenKind::*;
+
+    let mut tokens = tokens.clone();
+    if tokens.next()?.kind != LBracket || tokens.peek()?.kind == RBracket {
+        return None;
+    }
+
+    let mut depth = 1;
+    while let Some(t) = tokens.next() {
+        match t.kind {
+            LBracket => depth += 1,
+            RBracket if depth == 1 => return Some(tokens),
+            RBracket => depth -= 1,
+            Newline | HardBreak => return None,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Whether the tokens begin with a link's title followed by "(", i.e. the
+/// start of a link.
+fn link_ahead(tokens: &Tokens) -> bool {
+    after_link_title(tokens).is_some_and(|t| t.peek().is_some_and(|t| t.kind == TokenKind::LParen))
+}
+
+/// Whether the tokens begin with a link's title followed by "[",
+/// (optionally) text, "]", i.e. a reference link.
+fn ref_link_ahead(tokens: &Tokens) -> bool {
+    use TokenKind::*;
+
+    let mut tokens = match after_link_title(tokens) {
+        Some(tokens) => tokens,
+        None => return false,
+    };
+    let mut next = || tokens.next().map(|t| t.kind);
+
+    next() == Some(LBracket)
+        && match next() {
+            Some(RBracket) => true,
+            Some(kind) => is_text(kind) && next() == Some(RBracket),
+            None => false,
+        }
+}
+
+/// Parses "[title][label]", or "[title][]" whose label is its title.
+fn parse_ref_link(
+    tokens: &mut Tokens,
+    ctx: &mut Context,
+) -> Result<TextElement, JustTextError<'static>> {
+    use TokenKind::*;
+
+    let (title, title_span) = parse_link_title(tokens, ctx)?;
+
+    // As with `parse_link`, markup in the title can contain what
+    // `ref_link_ahead` took for the "][" ending it
+    let incomplete = || JustTextError::new("Incomplete link");
+    if tokens.next().map(|t| t.kind) != Some(LBracket) {
+        return Err(incomplete());
+    }
+    let (label, end) = match tokens.next() {
+        Some(t) if t.kind == RBracket => (title_span, t.span.1),
+        Some(t) if is_text(t.kind) => match tokens.next() {
+            Some(close) if close.kind == RBracket => (t.span, close.span.1),
+            _ => return Err(incomplete()),
+        },
+        _ => return Err(incomplete()),
+    };
+
+    Ok(TextElement::RefLink {
+        title,
+        label,
+        span: (title_span.0 - 1, end),
+    })
+}
+
+/// Parses a link's "[title]", which can contain other inline markup (but not
+/// links), and returns its parts and the span between the brackets.
+fn parse_link_title(
+    tokens: &mut Tokens,
+    ctx: &mut Context,
+) -> Result<(Vec<TextElement>, Span), JustTextError<'static>> {
+    use TokenKind::*;
+
+    let start = tokens.next().unwrap().span.1;
+    let mut parts = Vec::new();
+    let mut depth = 0;
+
+    loop {
+        let t = match tokens.peek() {
+            Some(t) => t,
+            None => return Err(JustTextError::new("Incomplete link")),
+        };
+        match t.kind {
+            RBracket if depth == 0 => {
+                tokens.next();
+                return Ok((parts, (start, t.span.0)));
+            }
+            Newline | HardBreak => return Err(JustTextError::new("Incomplete link")),
+            Backtick | DoubleDollar | FootnoteRef => parts.push(parse_text_element(tokens, ctx)?),
+            Star { opens: true, .. } if emphasis_ahead(tokens) => {
+                parts.push(parse_text_element(tokens, ctx)?)
+            }
+            Dollar { opens: true, .. } if math_ahead(tokens) => {
+                parts.push(parse_text_element(tokens, ctx)?)
+            }
+            // Anything else (including a bare URL) is text
+            kind => {
+                match kind {
+                    LBracket => depth += 1,
+                    RBracket => depth -= 1,
+                    _ => {}
+                }
+                let span = tokens.next().unwrap().span;
+                match parts.last_mut() {
+                    Some(TextElement::Text((_, end))) if *end == span.0 => *end = span.1,
+                    _ => parts.push(TextElement::Text(span)),
+                }
+            }
+        }
+    }
+}
+
+/// Parses "[label]: href", where the href is the rest of the line (optionally
+/// wrapped in "<...>").
+fn parse_link_def(tokens: &mut Tokens, ctx: &Context) -> Result<Block, JustTextError<'static>> {
+    let marker = tokens.next().unwrap().span;
+    let label = (
+        marker.0 + 1,
+        marker.0 + ctx.content[marker.0..].find(']').unwrap(),
+    );
+
+    let mut href = (marker.1, marker.1);
+    while let Some(t) = tokens.peek() {
+        if let TokenKind::Newline | TokenKind::HardBreak = t.kind {
+            break;
+        }
+        href.1 = tokens.next().unwrap().span.1;
+    }
+    href.1 -= ctx.content[href.0..href.1].len() - ctx.content[href.0..href.1].trim_end().len();
+    // As in links, the href can be wrapped in "<...>"
+    let trimmed = &ctx.content[href.0..href.1];
+    if trimmed.len() > 1 && trimmed.starts_with('<') && trimmed.ends_with('>') {
+        href = (href.0 + 1, href.1 - 1);
+    }
+
+    if href.0 == href.1 {
+        return Err(JustTextError::at(
+            format!(
+                "Link reference \"{}\" has no URL",
+                &ctx.content[label.0..label.1]
+            ),
+            marker.0,
         ));
     }
 
-    Ok(TextElement::Link {
-        title: title_span,
-        href: href_span,
-    })
-}
+    Ok(Block::LinkDef { label, href })
+}
+
+/// Bare URLs can be a link's title or href, like any other text.
+fn is_text(kind: TokenKind) -> bool {
+    matches!(kind, TokenKind::Text | TokenKind::Autolink)
+}
+
+fn parse_text(tokens: &mut Tokens) -> TextElement {
+    use TokenKind::*;
+
+    let (start, mut end) = tokens.next().unwrap().span;
+
+    while let Some(kind) = tokens.peek().map(|t| t.kind) {
+        match kind {
+            Backtick
+            | Fence(_)
+            | Newline
+            | HardBreak
+            | Pipe
+            | FootnoteRef
+            | DoubleDollar
+            | Autolink
+            | Star { .. } => {
+                break;
+            }
+            Dollar { opens: true, .. } if math_ahead(tokens) => {
+                break;
+            }
+            LBracket if link_ahead(tokens) || ref_link_ahead(tokens) => {
+                break;
+            }
+            _ => {
+                end = tokens.next().unwrap().span.1;
+            }
+        }
+    }
+
+    TextElement::Text((start, end))
+}
+
+/// Numbers footnotes in the order they're first referenced.
+fn parse_footnote_ref(tokens: &mut Tokens, ctx: &mut Context) -> TextElement {
+    let span = tokens.next().unwrap().span;
+    let label = &ctx.content[span.0 + 2..span.1 - 1];
+
+    let number = match ctx.footnotes.iter().position(|f| f.label == label) {
+        Some(i) => i + 1,
+        None => {
+            ctx.footnotes.push(Footnote {
+                label: label.to_string(),
+                pos: span.0,
+                refs: 0,
+            });
+            ctx.footnotes.len()
+        }
+    };
+    let footnote = &mut ctx.footnotes[number - 1];
+    footnote.refs += 1;
+
+    TextElement::FootnoteRef {
+        span,
+        number,
+        occurrence: footnote.refs,
+    }
+}
+
+/// Whether the tokens begin with "$" and the next "$" in the same paragraph
+/// can close it, i.e. whether they begin with inline math.
+fn math_ahead(tokens: &Tokens) -> bool {
+    let mut tokens = tokens.clone();
+    tokens.next();
+
+    let mut prev_newline = false;
+    for t in tokens {
+        match t.kind {
+            TokenKind::Dollar { closes, .. } => return closes,
+            TokenKind::Newline if prev_newline => return false,
+            _ => {}
+        }
+        prev_newline = t.kind == TokenKind::Newline;
+    }
+
+    false
+}
+
+/// Whether the tokens begin with "*" (or "**") and a matching one that can
+/// close it follows in the same paragraph, i.e. whether they begin with
+/// emphasis.
+fn emphasis_ahead(tokens: &Tokens) -> bool {
+    let mut tokens = tokens.clone();
+    let strong = match tokens.next().map(|t| t.kind) {
+        Some(TokenKind::Star { strong, .. }) => strong,
+        _ => return false,
+    };
+
+    let mut prev_newline = false;
+    for t in tokens {
+        match t.kind {
+            TokenKind::Star {
+                strong: s,
+                closes: true,
+                ..
+            } if s == strong => return true,
+            TokenKind::Newline if prev_newline => return false,
+            _ => {}
+        }
+        prev_newline = t.kind == TokenKind::Newline;
+    }
+
+    false
+}
+
+fn parse_emphasis(
+    tokens: &mut Tokens,
+    ctx: &mut Context,
+) -> Result<TextElement, JustTextError<'static>> {
+    use TokenKind::*;
+
+    let strong = match tokens.next().unwrap().kind {
+        Star { strong, .. } => strong,
+        _ => unreachable!(),
+    };
+    let mut parts = Vec::new();
+
+    while let Some(t) = tokens.peek() {
+        match t.kind {
+            Star {
+                strong: s,
+                closes: true,
+                ..
+            } if s == strong => {
+                tokens.next();
+                return Ok(TextElement::Emphasis { strong, parts });
+            }
+            Newline | HardBreak => {
+                let line_end = tokens.next().unwrap();
+                if tokens
+                    .peek()
+                    .is_none_or(|t| t.kind == Newline || starts_block(&t.kind))
+                {
+                    break;
+                }
+                parts.push(TextElement::LineBreak {
+                    span: line_end.span,
+                    hard: line_end.kind == HardBreak || ctx.options.preserve_lines,
+                });
+            }
+            _ => parts.push(parse_text_element(tokens, ctx)?),
+        }
+    }
+
+    Err(JustTextError::new("Unterminated emphasis"))
+}
+
+fn parse_math(tokens: &mut Tokens, content: &str) -> Result<TextElement, JustTextError<'static>> {
+    let open = tokens.next().unwrap();
+    let display = open.kind == TokenKind::DoubleDollar;
+
+    // Like `math_ahead`, the search ends with the paragraph
+    let mut prev_newline = false;
+    let close = loop {
+        let t = match tokens.next() {
+            Some(t) => t,
+            None => break None,
+        };
+        match t.kind {
+            TokenKind::Dollar { .. } if !display => break Some(t),
+            TokenKind::DoubleDollar if display => break Some(t),
+            TokenKind::Newline if prev_newline => break None,
+            _ => {}
+        }
+        prev_newline = t.kind == TokenKind::Newline;
+    };
+    let close = match close {
+        Some(close) => close,
+        None if display => return Err(JustTextError::new("Unterminated display math")),
+        None => return Err(JustTextError::new("Unterminated inline math")),
+    };
+
+    let tex = (open.span.1, close.span.0);
+    match to_mathml(&content[tex.0..tex.1], display) {
+        Ok(mathml) => Ok(TextElement::Math { tex, mathml }),
+        Err(e) => Err(JustTextError::at(
+            format!("Invalid math: {}", e.message),
+            tex.0 + e.offset,
+        )),
+    }
+}
+
+fn parse_mono(tokens: &mut Tokens) -> Result<TextElement, JustTextError<'static>> {
+    use TokenKind::*;
+
+    let start = tokens.next().unwrap().span.1;
+    let mut end = start;
+
+    loop {
+        let next = tokens.next();
+        if next.is_none() {
+            return Err(JustTextError::new("Unterminated inline mono"));
+        }
+        let next = next.unwrap();
+
+        match next.kind {
+            Backtick => {
+                break;
+            }
+            _ => {
+                end = next.span.1;
+            }
+        }
+    }
+
+    Ok(TextElement::Mono((start, end)))
+}
+
+fn parse_block_mono(
+    tokens: &mut Tokens,
+    quote_depth: usize,
+) -> Result<TextElement, JustTextError<'static>> {
+    use TokenKind::*;
+
+    let fence = tokens.next().unwrap();
+    let fence_len = match fence.kind {
+        Fence(len) => len,
+        _ => unreachable!(),
+    };
+    let indent = fence.span.1 - fence.span.0 - fence_len;
+
+    let start = fence.span.1;
+    let mut info = None;
+    let mut code_start = start;
+    let mut end = start;
+    let mut on_opening_line = true;
+    let mut at_line_start = false;
+    // Where the previous token began, if it was a newline
+    let mut prev_newline = None;
+
+    loop {
+        let next = tokens.next();
+        if next.is_none() {
+            return Err(JustTextError::new("Unterminated block mono"));
+        }
+        let next = next.unwrap();
+        let may_close = on_opening_line || at_line_start;
+        at_line_start = matches!(next.kind, Newline | HardBreak | QuoteMarker);
+
+        match next.kind {
+            // Shorter fences are part of the code, which allows fences to be
+            // quoted, as are fences in the middle of a line of code
+            Fence(len) if len >= fence_len && may_close => {
+                // The newline preceding a closing fence on its own line isn't part
+                // of the code
+                if let Some(newline_start) = prev_newline {
+                    end = newline_start;
+                }
+                break;
+            }
+            // Anything between the opening fence and the end of its line is the
+            // info string, unless the block closes on the same line
+            Newline | HardBreak if on_opening_line => {
+                on_opening_line = false;
+                if next.span.0 > start {
+                    info = Some((start, next.span.0));
+                }
+                code_start = next.span.1;
+                end = code_start;
+            }
+            Newline => {
+                end = next.span.1;
+                prev_newline = Some(next.span.0);
+            }
+            HardBreak => {
+                end = next.span.1;
+                prev_newline = Some(next.span.1 - 1);
+            }
+            _ => {
+                end = next.span.1;
+                prev_newline = None;
+            }
+        }
+    }
+
+    Ok(TextElement::BlockMono {
+        info,
+        code: (code_start, end),
+        indent,
+        quote_depth,
+    })
+}
+
+/// Parses "[title](href)" or "[title](href "tooltip")". The href can be
+/// wrapped in "<...>" so that it can contain spaces and unbalanced parens.
+fn parse_link(
+    tokens: &mut Tokens,
+    ctx: &mut Context,
+) -> Result<TextElement, JustTextError<'static>> {
+    let (title, _) = parse_link_title(tokens, ctx)?;
+
+    // `link_ahead` doesn't know about markup in the title, which can contain
+    // what it took for the "](" ending the title
+    let open = match tokens.next() {
+        Some(t) if t.kind == TokenKind::LParen => t.span,
+        _ => return Err(JustTextError::new("Incomplete link")),
+    };
+    let (href, tooltip, close) = parse_link_destination(ctx.content, open.1)?;
+
+    // ")" is always a token of its own
+    if !tokens.any(|t| t.span.0 == close) {
+        return Err(JustTextError::new("Incomplete link"));
+    }
+
+    Ok(TextElement::Link {
+        title,
+        href,
+        tooltip,
+    })
+}
+
+/// Parses what follows a link's "(" at `start`, returning the href, tooltip,
+/// and the position of the closing ")".
+fn parse_link_destination(
+    content: &str,
+    start: usize,
+) -> Result<(Span, Option<Span>, usize), JustTextError<'static>> {
+    let line_end = content[start..]
+        .find('\n')
+        .map_or(content.len(), |n| start + n);
+    let skip_spaces = |pos: usize| {
+        let rest = &content[pos..line_end];
+        pos + rest.len() - rest.trim_start().len()
+    };
+
+    let mut pos = skip_spaces(start);
+    let href = if content[pos..line_end].starts_with('<') {
+        match content[pos..line_end].find('>') {
+            Some(n) => {
+                let href = (pos + 1, pos + n);
+                pos += n + 1;
+                href
+            }
+            None => {
+                return Err(JustTextError::at(
+                    "Invalid link: expected \">\" after \"<\"",
+                    pos,
+                ))
+            }
+        }
+    } else {
+        // Parens are allowed as long as they're balanced
+        let mut depth = 0;
+        let mut escaped = false;
+        let mut end = line_end;
+        for (i, c) in content[pos..line_end].char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '(' => depth += 1,
+                ')' if depth == 0 => {
+                    end = pos + i;
+                    break;
+                }
+                ')' => depth -= 1,
+                c if c.is_whitespace() => {
+                    end = pos + i;
+                    break;
+                }
+                _ => {}
+            }
+        }
+        if end == pos {
+            return Err(JustTextError::at(
+                "Invalid link: expected a URL after \"(\"",
+                pos,
+            ));
+        }
+        let href = (pos, end);
+        pos = end;
+        href
+    };
+
+    let mut tooltip = None;
+    let after_href = pos;
+    pos = skip_spaces(pos);
+    if let Some(quote) = content[pos..line_end]
+        .chars()
+        .next()
+        .filter(|c| *c == '"' || *c == '\'')
+    {
+        if pos == after_href {
+            return Err(JustTextError::at(
+                "Invalid link: expected a space before the title",
+                pos,
+            ));
+        }
+        // The title can contain escaped quotes
+        let title = &content[pos + 1..line_end];
+        let close = title
+            .char_indices()
+            .find(|&(i, c)| c == quote && !title[..i].ends_with('\\'))
+            .map(|(i, _)| i);
+        match close {
+            Some(n) => {
+                tooltip = Some((pos + 1, pos + 1 + n));
+                pos = skip_spaces(pos + n + 2);
+            }
+            None => return Err(JustTextError::at("Invalid link: unterminated title", pos)),
+        }
+    }
+
+    match content[pos..line_end].chars().next() {
+        Some(')') => Ok((href, tooltip, pos)),
+        Some(_) => Err(JustTextError::at(
+            "Invalid link: expected \")\" after URL",
+            pos,
+        )),
+        None => Err(JustTextError::new("Incomplete link")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lex(input: &str) -> Vec<Token> {
+        Lexer::new(input).collect()
+    }
+
+    fn context(content: &'static str) -> Context<'static> {
+        Context {
+            content,
+            options: Options::default(),
+            warnings: vec![],
+            quote_depth: 0,
+            footnotes: vec![],
+        }
+    }
+
+    #[test]
+    fn parse_link1() {
+        let input = "[a link](here)";
+        //           01234567890123
+
+        assert_eq!(
+            parse_link(&mut Tokens::new(&lex(input)), &mut context(input)),
+            Ok(TextElement::Link {
+                title: vec![TextElement::Text((1, 7))],
+                href: (9, 13),
+                tooltip: None
+            })
+        );
+    }
+
+    #[test]
+    fn parse_link2() {
+        let input = "[the `foo` *fn*](<a b(.md> \"A \\\"tip\\\"\")";
+        //           0123456789012345678901234567 8 9012345 6 7
+
+        assert_eq!(
+            parse_link(&mut Tokens::new(&lex(input)), &mut context(input)),
+            Ok(TextElement::Link {
+                title: vec![
+                    TextElement::Text((1, 5)),
+                    TextElement::Mono((6, 9)),
+                    TextElement::Text((10, 11)),
+                    TextElement::Emphasis {
+                        strong: false,
+                        parts: vec![TextElement::Text((12, 14))]
+                    }
+                ],
+                href: (18, 25),
+                tooltip: Some((28, 37))
+            })
+        );
+    }
+
+    #[test]
+    fn parse_link3() {
+        // Inline code in the title contains what looks like the title's end
+        let input = "[a `](`]";
+        let note = parse(input, Options::default()).unwrap();
+
+        assert_eq!(
+            note.resolve(input),
+            "<p>[a <span class=\"mono\">](</span>]</p>"
+        );
+        assert_eq!(
+            note.warnings,
+            vec![Warning {
+                message: String::from("Incomplete link"),
+                pos: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn resolve_links1() {
+        let input = "See [the **`foo`** function](wiki/Foo_(bar) 'It \\'s <here>') or
+[*the* book][Book], but 2 * 3 and a*b aren't emphasis.
+
+[book]: <https://example.com/a b>";
+        let note = parse(input, Options::default()).unwrap();
+
+        assert_eq!(
+            note.resolve(input),
+            "<p>See <a href=\"wiki/Foo_(bar)\" title=\"It 's &lt;here&gt;\">the \
+             <strong><span class=\"mono\">foo</span></strong> function</a> or\n\
+             <a href=\"https://example.com/a b\"><em>the</em> book</a>, \
+             but 2 * 3 and a*b aren't emphasis.</p>"
+        );
+    }
+
+    #[test]
+    fn resolve_rules1() {
+        let input = "Monday
+* * *
+Tuesday
+
+> Quoted
+> ---
+> rule
+---
+
+[x]: https://example.com";
+        let note = parse(input, Options::default()).unwrap();
+
+        assert_eq!(
+            note.resolve(input),
+            "<section class=\"note-section\" id=\"section-1\"><p>Monday</p></section><hr>\
+             <section class=\"note-section\" id=\"section-2\"><p>Tuesday</p>\
+             <blockquote><p>Quoted</p><hr><p>rule</p></blockquote></section>"
+        );
+
+        let input = "No - sections -- here";
+        let note = parse(input, Options::default()).unwrap();
+        assert_eq!(note.resolve(input), "<p>No - sections -- here</p>");
+    }
+
+    #[test]
+    fn parse_mono1() {
+        let input = "`Some monospace text`";
+        //           012345678901234567890
+
+        assert_eq!(
+            parse_mono(&mut Tokens::new(&lex(input))),
+            Ok(TextElement::Mono((1, 20)))
+        );
+    }
+
+    #[test]
+    fn resolve_code_includes1() {
+        let input = "```include  src/a.rs#L1-L2\n```";
+        //           0123456789012
+        let mut note = parse(input, Options::default()).unwrap();
+
+        assert_eq!(
+            note.code_include_specs(input),
+            vec![(String::from("src/a.rs#L1-L2"), 12)]
+        );
+
+        note.code_includes = vec![(String::from("src/a.rs#L1-L2"), String::from("x < y"))];
+        assert_eq!(
+            note.resolve(input),
+            "<pre><code class=\"language-rust\">x &lt; y</code></pre>"
+        );
+    }
+
+    #[test]
+    fn resolve_comments1() {
+        let input = "One\n%% a draft\ntwo\n\n%%\nHidden paragraph.\n%%\n\
+                     ```\n%% kept\n%%\n```\n\n> %% quoted\n> three";
+        let note = parse(input, Options::default()).unwrap();
+
+        assert_eq!(
+            note.resolve(input),
+            "<p>One\ntwo</p><pre><code>%% kept\n%%</code></pre>\
+             <blockquote><p>three</p></blockquote>"
+        );
+        assert_eq!(note.word_count, 10);
+    }
+
+    #[test]
+    fn parse_block_mono1() {
+        let input = "```This is some block mono```";
+        //           01234567890123456789012345678
+
+        assert_eq!(
+            parse_block_mono(&mut Tokens::new(&lex(input)), 0),
+            Ok(TextElement::BlockMono {
+                info: None,
+                code: (3, 26),
+                indent: 0,
+                quote_depth: 0
+            })
+        );
+    }
+
+    #[test]
+    fn parse_block_mono2() {
+        let input = "``` rust
+let x = 1;
+```";
+
+        assert_eq!(
+            parse_block_mono(&mut Tokens::new(&lex(input)), 0),
+            Ok(TextElement::BlockMono {
+                info: Some((3, 8)),
+                code: (9, 19),
+                indent: 0,
+                quote_depth: 0
+            })
+        );
+    }
+
+    #[test]
+    fn parse_block_mono3() {
+        let input = "  ````
+    ```
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    indented
+  ````";
+
+        assert_eq!(
+            parse_block_mono(&mut Tokens::new(&lex(input)), 0),
+            Ok(TextElement::BlockMono {
+                info: None,
+                code: (7, 28),
+                indent: 2,
+                quote_depth: 0
+            })
+        );
+    }
 
     #[test]
-    fn parse_link1() {
-        let input = "[a link](here)";
-        //           01234567890123
+    fn parse_block_mono4() {
+        let input = "```
+return \"```\";
+```";
 
         assert_eq!(
-            parse_link(&mut Lexer::new(input).peekable()),
-            Ok(TextElement::Link {
-                title: (1, 7),
-                href: (9, 13)
+            parse_block_mono(&mut Tokens::new(&lex(input)), 0),
+            Ok(TextElement::BlockMono {
+                info: None,
+                code: (4, 17),
+                indent: 0,
+                quote_depth: 0
             })
         );
     }
 
     #[test]
-    fn parse_mono1() {
-        let input = "`Some monospace text`";
-        //           012345678901234567890
+    fn parse_block_mono5() {
+        // The opening fence's line ends in a hard break
+        let input = "```rust\\\nx\n```";
 
         assert_eq!(
-            parse_mono(&mut Lexer::new(input).peekable()),
-            Ok(TextElement::Mono((1, 20)))
+            parse_block_mono(&mut Tokens::new(&lex(input)), 0),
+            Ok(TextElement::BlockMono {
+                info: Some((3, 7)),
+                code: (9, 10),
+                indent: 0,
+                quote_depth: 0
+            })
         );
+
+        let input = "Intro\n\n```\\\n\n```\n";
+        let note = parse(input, Options::default()).unwrap();
+        assert_eq!(note.resolve(input), "<p>Intro</p><pre><code></code></pre>");
     }
 
     #[test]
-    fn parse_block_mono1() {
-        let input = "```This is some block mono```";
-        //           01234567890123456789012345678
+    fn resolve_block_mono1() {
+        let input = "Before
+  ```
+  if x:
+
+      y()
+  ```
+After";
+        let note = parse(input, Options::default()).unwrap();
 
         assert_eq!(
-            parse_block_mono(&mut Lexer::new(input).peekable()),
-            Ok(TextElement::Mono((3, 26)))
+            note.resolve(input),
+            "<p>Before</p><pre><code>if x:\n\n    y()</code></pre><p>After</p>"
         );
     }
 
@@ -328,21 +2832,81 @@ mod tests {
         //           01234567890123456789012345678901234567890
 
         assert_eq!(
-            parse_paragraph(&mut Lexer::new(input).peekable()),
+            parse_paragraph(&mut Tokens::new(&lex(input)), &mut context(input)),
             Ok(Paragraph {
                 parts: vec![
                     TextElement::Text((0, 10)),
                     TextElement::Mono((11, 20)),
                     TextElement::Text((21, 27)),
                     TextElement::Link {
-                        title: (28, 34),
-                        href: (36, 40)
+                        title: vec![TextElement::Text((28, 34))],
+                        href: (36, 40),
+                        tooltip: None
+                    }
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn parse_paragraph2() {
+        let input = "f(x) [sic] \\[not](a link) ) [link](here)";
+
+        assert_eq!(
+            parse_paragraph(&mut Tokens::new(&lex(input)), &mut context(input)),
+            Ok(Paragraph {
+                parts: vec![
+                    TextElement::Text((0, 28)),
+                    TextElement::Link {
+                        title: vec![TextElement::Text((29, 33))],
+                        href: (35, 39),
+                        tooltip: None
                     }
                 ]
             })
         );
     }
 
+    #[test]
+    fn resolve_escapes1() {
+        let input = "\\`not mono\\` and \\\\ but C:\\dir";
+        let note = parse(input, Options::default()).unwrap();
+
+        assert_eq!(note.resolve(input), "<p>`not mono` and \\ but C:\\dir</p>");
+    }
+
+    #[test]
+    fn resolve_line_breaks1() {
+        let input = "Soft
+hard\\
+also hard  
+end
+
+Next";
+        let note = parse(input, Options::default()).unwrap();
+
+        assert_eq!(
+            note.resolve(input),
+            "<p>Soft\nhard<br>\nalso hard  <br>\nend</p><p>Next</p>"
+        );
+    }
+
+    #[test]
+    fn resolve_line_breaks2() {
+        let input = "Roses are red
+Violets are blue";
+        let options = Options {
+            preserve_lines: true,
+            ..Options::default()
+        };
+        let note = parse(input, options).unwrap();
+
+        assert_eq!(
+            note.resolve(input),
+            "<p>Roses are red<br>\nViolets are blue</p>"
+        );
+    }
+
     #[test]
     fn parse1() {
         let input = "This is a note
@@ -350,17 +2914,623 @@ mod tests {
 with a couple of lines";
 
         assert_eq!(
-            parse(input),
+            parse(input, Options::default()),
             Ok(Note {
-                paragraphs: vec![
-                    Paragraph {
+                blocks: vec![
+                    Block::Paragraph(Paragraph {
                         parts: vec![TextElement::Text((0, 14))]
-                    },
-                    Paragraph {
+                    }),
+                    Block::Paragraph(Paragraph {
                         parts: vec![TextElement::Text((16, 38))]
+                    })
+                ],
+                emoji: true,
+                word_count: 9,
+                ..Note::default()
+            })
+        );
+    }
+
+    #[test]
+    fn parse2() {
+        let input = "# Intro
+Some text
+## The `foo` function
+[toc]";
+
+        assert_eq!(
+            parse(input, Options::default()),
+            Ok(Note {
+                blocks: vec![
+                    Block::Heading {
+                        level: 1,
+                        parts: vec![TextElement::Text((2, 7))]
+                    },
+                    Block::Paragraph(Paragraph {
+                        parts: vec![TextElement::Text((8, 17))]
+                    }),
+                    Block::Heading {
+                        level: 2,
+                        parts: vec![
+                            TextElement::Text((21, 25)),
+                            TextElement::Mono((26, 29)),
+                            TextElement::Text((30, 39))
+                        ]
+                    },
+                    Block::Toc
+                ],
+                emoji: true,
+                word_count: 9,
+                ..Note::default()
+            })
+        );
+    }
+
+    #[test]
+    fn resolve_headings1() {
+        let input = "# Setup
+## Setup
+# Setup";
+        let note = parse(input, Options::default()).unwrap();
+
+        assert_eq!(
+            note.resolve(input),
+            "<h2 id=\"setup\">Setup<a class=\"permalink\" href=\"#setup\" \
+             aria-label=\"Link to this section\">¶</a></h2>\
+             <h3 id=\"setup-2\">Setup<a class=\"permalink\" href=\"#setup-2\" \
+             aria-label=\"Link to this section\">¶</a></h3>\
+             <h2 id=\"setup-3\">Setup<a class=\"permalink\" href=\"#setup-3\" \
+             aria-label=\"Link to this section\">¶</a></h2>"
+        );
+    }
+
+    #[test]
+    fn resolve_toc1() {
+        let input = "[toc]\n# Release :tada: \"done\"";
+        let options = Options {
+            typography: true,
+            ..Options::default()
+        };
+        let note = parse(input, options).unwrap();
+
+        assert!(note.resolve(input).starts_with(
+            "<nav class=\"toc\"><ul><li><a href=\"#release-tada-done\">\
+             Release 🎉 \u{201c}done\u{201d}</a></li></ul></nav>"
+        ));
+    }
+
+    #[test]
+    fn resolve_headings2() {
+        let input = "# Über Café
+# Task 1
+- [ ] Do it";
+        let note = parse(input, Options::default()).unwrap();
+
+        assert_eq!(note.anchors(input), vec!["task-1", "über-café", "task-1-2"]);
+    }
+
+    #[test]
+    fn parse_lenient1() {
+        let input = "An `unterminated mono and [a](link";
+
+        assert_eq!(
+            parse(input, Options::default()),
+            Ok(Note {
+                blocks: vec![Block::Paragraph(Paragraph {
+                    parts: vec![
+                        TextElement::Text((0, 3)),
+                        TextElement::Text((3, 26)),
+                        TextElement::Text((26, 34))
+                    ]
+                })],
+                warnings: vec![
+                    Warning {
+                        message: String::from("Unterminated inline mono"),
+                        pos: 3
+                    },
+                    Warning {
+                        message: String::from("Incomplete link"),
+                        pos: 26
                     }
-                ]
+                ],
+                emoji: true,
+                word_count: 5,
+                ..Note::default()
             })
         );
     }
+
+    #[test]
+    fn parse_strict1() {
+        let input = "An `unterminated mono";
+
+        assert_eq!(
+            parse(
+                input,
+                Options {
+                    strict: true,
+                    ..Options::default()
+                }
+            ),
+            Err(JustTextError::new("Unterminated inline mono"))
+        );
+    }
+
+    #[test]
+    fn resolve_quotes1() {
+        let input = "> Quoted
+> text
+>
+> > Nested
+> > ```
+> > code
+> > ```
+After";
+        let note = parse(input, Options::default()).unwrap();
+
+        assert_eq!(
+            note.resolve(input),
+            "<blockquote><p>Quoted\ntext</p><blockquote><p>Nested</p>\
+             <pre><code>code</code></pre></blockquote></blockquote><p>After</p>"
+        );
+    }
+
+    #[test]
+    fn resolve_callouts1() {
+        let input = "> [!WARNING] Mind the `gap`
+> Careful.
+
+> [!note]
+> - a";
+        let note = parse(input, Options::default()).unwrap();
+
+        assert_eq!(
+            note.resolve(input),
+            "<aside class=\"callout callout-warning\" role=\"note\">\
+             <p class=\"callout-title\">Mind the <span class=\"mono\">gap</span></p>\
+             <p>Careful.</p></aside>\
+             <aside class=\"callout callout-note\" role=\"note\">\
+             <p class=\"callout-title\">Note</p><ul><li>a</li></ul></aside>"
+        );
+    }
+
+    #[test]
+    fn resolve_lists1() {
+        let input = "Items:
+- one
+  continued
+  1. a
+  2. b
+
+- two
+Done";
+        let note = parse(input, Options::default()).unwrap();
+
+        assert_eq!(
+            note.resolve(input),
+            "<p>Items:</p><ul><li>one\n  continued<ol><li>a</li><li>b</li></ol></li>\
+             <li>two\nDone</li></ul>"
+        );
+    }
+
+    #[test]
+    fn resolve_tasks1() {
+        let input = "- [ ] Buy `milk`
+- [x] Walk
+  - [ ] Nested
+- Plain [ ]";
+        let note = parse(input, Options::default()).unwrap();
+
+        assert_eq!(
+            note.resolve(input),
+            "<ul><li class=\"task\" id=\"task-1\"><input type=\"checkbox\" disabled> \
+             Buy <span class=\"mono\">milk</span></li>\
+             <li class=\"task\" id=\"task-2\"><input type=\"checkbox\" disabled checked> Walk\
+             <ul><li class=\"task\" id=\"task-3\"><input type=\"checkbox\" disabled> Nested</li></ul></li>\
+             <li>Plain [ ]</li></ul>"
+        );
+        assert_eq!(
+            note.tasks(input),
+            vec![
+                Task {
+                    pos: 0,
+                    text: String::from("Buy milk"),
+                    done: false
+                },
+                Task {
+                    pos: 17,
+                    text: String::from("Walk"),
+                    done: true
+                },
+                Task {
+                    pos: 28,
+                    text: String::from("Nested"),
+                    done: false
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_definitions1() {
+        let input = "Lexer
+  : Splits *text* into tokens
+  : Also a scanner
+
+Parser
+  : Builds blocks
+Not a term
+: not a definition";
+        let note = parse(input, Options::default()).unwrap();
+
+        assert_eq!(
+            note.resolve(input),
+            "<dl><dt id=\"term-lexer\">Lexer</dt><dd>Splits <em>text</em> into tokens</dd>\
+             <dd>Also a scanner</dd><dt id=\"term-parser\">Parser</dt><dd>Builds blocks</dd></dl>\
+             <p>Not a term\n: not a definition</p>"
+        );
+        assert_eq!(
+            note.terms(input),
+            vec![
+                Term {
+                    id: String::from("term-lexer"),
+                    term: String::from("Lexer"),
+                    definitions: vec![
+                        String::from("Splits text into tokens"),
+                        String::from("Also a scanner")
+                    ]
+                },
+                Term {
+                    id: String::from("term-parser"),
+                    term: String::from("Parser"),
+                    definitions: vec![String::from("Builds blocks")]
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_typography1() {
+        let input = "\"Don't\" -- `it's` 1--2 [a 'b'](x--y) ... \\\"raw\\\" --- `x`'s";
+        let options = Options {
+            typography: true,
+            ..Options::default()
+        };
+        let note = parse(input, options).unwrap();
+
+        assert_eq!(
+            note.resolve(input),
+            "<p>\u{201c}Don\u{2019}t\u{201d} \u{2013} <span class=\"mono\">it's</span> 1\u{2013}2 \
+             <a href=\"x--y\">a \u{2018}b\u{2019}</a> \u{2026} \"raw\" \u{2014} \
+             <span class=\"mono\">x</span>\u{2019}s</p>"
+        );
+
+        let note = parse(input, Options::default()).unwrap();
+        assert!(note.resolve(input).starts_with("<p>\"Don't\" -- "));
+    }
+
+    #[test]
+    fn resolve_typography2() {
+        let input = "She said *\"hi\"*, **'yo'**, and \"*ok*\".";
+        let options = Options {
+            typography: true,
+            ..Options::default()
+        };
+        let note = parse(input, options).unwrap();
+
+        assert_eq!(
+            note.resolve(input),
+            "<p>She said <em>\u{201c}hi\u{201d}</em>, <strong>\u{2018}yo\u{2019}</strong>, \
+             and \u{201c}<em>ok</em>\u{201d}.</p>"
+        );
+    }
+
+    #[test]
+    fn resolve_emoji1() {
+        let input = "Shipped :rocket: at 10:30:00 [:tada:](x) `:fire:` \\:fire: :nope:\n\
+                     ```\n:bug:\n```";
+        let note = parse(input, Options::default()).unwrap();
+
+        assert_eq!(
+            note.resolve(input),
+            "<p>Shipped 🚀 at 10:30:00 <a href=\"x\">🎉</a> <span class=\"mono\">:fire:</span> \
+             :fire: :nope:</p><pre><code>:bug:</code></pre>"
+        );
+        assert_eq!(
+            note.warnings,
+            vec![Warning {
+                message: String::from("Unknown emoji shortcode \":nope:\""),
+                pos: 58
+            }]
+        );
+
+        let options = Options {
+            emoji: false,
+            ..Options::default()
+        };
+        let note = parse(input, options).unwrap();
+        assert!(note.resolve(input).starts_with("<p>Shipped :rocket: "));
+        assert!(note.warnings.is_empty());
+    }
+
+    #[test]
+    fn resolve_mono1() {
+        let input = "Use `<b>x</b>` & `\"y\"`";
+        let note = parse(input, Options::default()).unwrap();
+
+        assert_eq!(
+            note.resolve(input),
+            "<p>Use <span class=\"mono\">&lt;b&gt;x&lt;/b&gt;</span> & \
+             <span class=\"mono\">&quot;y&quot;</span></p>"
+        );
+    }
+
+    #[test]
+    fn resolve_tables1() {
+        let input = "| Name | `Vec<u8>` | Size |
+|:-----|:------:|-----:|
+| [a](a.txt) | a \\| b | <1 |
+|  | x | 2 |
+| not | a | separator";
+        let note = parse(input, Options::default()).unwrap();
+
+        assert_eq!(
+            note.resolve(input),
+            "<table><thead><tr><th style=\"text-align: left\">Name</th>\
+             <th style=\"text-align: center\"><span class=\"mono\">Vec&lt;u8&gt;</span></th>\
+             <th style=\"text-align: right\">Size</th></tr></thead><tbody>\
+             <tr><td style=\"text-align: left\"><a href=\"a.txt\">a</a></td>\
+             <td style=\"text-align: center\">a | b</td>\
+             <td style=\"text-align: right\">&lt;1</td></tr>\
+             <tr><td style=\"text-align: left\"></td><td style=\"text-align: center\">x</td>\
+             <td style=\"text-align: right\">2</td></tr>\
+             <tr><td style=\"text-align: left\">not</td><td style=\"text-align: center\">a</td>\
+             <td style=\"text-align: right\">separator</td></tr></tbody></table>"
+        );
+    }
+
+    #[test]
+    fn parse_tables1() {
+        let input = "| a | b |
+| - | - |
+| 1 |";
+        //           0123456789012345678901
+
+        assert_eq!(
+            parse(input, Options::default()),
+            Err(JustTextError::at(
+                "Table row has 1 cell(s), but the table's header has 2",
+                20
+            ))
+        );
+
+        let input = "| Not a table |
+Just text";
+        let note = parse(input, Options::default()).unwrap();
+        assert_eq!(note.resolve(input), "<p>| Not a table |\nJust text</p>");
+    }
+
+    #[test]
+    fn resolve_footnotes1() {
+        let input = "A[^b] and[^a][^b] and [^c].
+
+[^a]: First.
+[^b]: Second
+  line.
+[^d]: Unused.";
+        let note = parse(input, Options::default()).unwrap();
+
+        assert_eq!(
+            note.resolve(input),
+            "<p>A<sup class=\"footnote-ref\"><a id=\"fnref-1\" href=\"#fn-1\">1</a></sup> \
+             and<sup class=\"footnote-ref\"><a id=\"fnref-2\" href=\"#fn-2\">2</a></sup>\
+             <sup class=\"footnote-ref\"><a id=\"fnref-1-2\" href=\"#fn-1\">1</a></sup> \
+             and [^c].</p><section class=\"footnotes\"><ol>\
+             <li id=\"fn-1\">Second\n  line. \
+             <a class=\"footnote-back\" href=\"#fnref-1\" aria-label=\"Back to reference\">\u{21a9}</a> \
+             <a class=\"footnote-back\" href=\"#fnref-1-2\" aria-label=\"Back to reference\">\
+             \u{21a9}<sup>2</sup></a></li>\
+             <li id=\"fn-2\">First. \
+             <a class=\"footnote-back\" href=\"#fnref-2\" aria-label=\"Back to reference\">\u{21a9}</a>\
+             </li></ol></section>"
+        );
+        assert_eq!(
+            note.warnings,
+            vec![
+                Warning {
+                    message: String::from("Footnote \"c\" isn't defined"),
+                    pos: 22
+                },
+                Warning {
+                    message: String::from("Footnote \"d\" is never referenced"),
+                    pos: 65
+                }
+            ]
+        );
+
+        let strict = Options {
+            strict: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            parse(input, strict),
+            Err(JustTextError::at("Footnote \"c\" isn't defined", 22))
+        );
+    }
+
+    #[test]
+    fn resolve_sidenotes1() {
+        let input = "- A[^1]
+
+[^1]: Note.";
+        let options = Options {
+            sidenotes: true,
+            ..Options::default()
+        };
+        let note = parse(input, options).unwrap();
+
+        assert_eq!(
+            note.resolve(input),
+            "<ul><li>A<sup class=\"footnote-ref\"><a id=\"fnref-1\" href=\"#fn-1\">1</a></sup>\
+             </li></ul><aside class=\"sidenote\" id=\"fn-1\"><sup>1</sup> Note.</aside>"
+        );
+    }
+
+    #[test]
+    fn resolve_math1() {
+        let input = "Costs $5 or $10, and $x_1$ is \\$x\\$.
+$$
+\\frac{1}{2}
+$$";
+        let note = parse(input, Options::default()).unwrap();
+
+        assert_eq!(
+            note.resolve(input),
+            "<p>Costs $5 or $10, and <math><msub><mi>x</mi><mn>1</mn></msub></math> is $x$.\n\
+             <math display=\"block\"><mfrac><mn>1</mn><mn>2</mn></mfrac></math></p>"
+        );
+    }
+
+    #[test]
+    fn parse_math1() {
+        let input = "See $a \\oops b$ here";
+        //           0123456789
+
+        let note = parse(input, Options::default()).unwrap();
+        assert_eq!(
+            note.warnings,
+            vec![Warning {
+                message: String::from("Invalid math: Unknown command \"\\oops\""),
+                pos: 7
+            }]
+        );
+        assert_eq!(note.resolve(input), "<p>See $a \\oops b$ here</p>");
+    }
+
+    #[test]
+    fn parse_math2() {
+        // An unclosed "$$" doesn't reach into later blocks
+        let input = "Sum $$x\n\n# Later\n\nThen $$y$$";
+        //           01234
+
+        let note = parse(input, Options::default()).unwrap();
+        assert_eq!(
+            note.warnings,
+            vec![Warning {
+                message: String::from("Unterminated display math"),
+                pos: 4
+            }]
+        );
+        assert_eq!(
+            note.resolve(input),
+            "<p>Sum $$x</p><h2 id=\"later\">Later<a class=\"permalink\" href=\"#later\" \
+             aria-label=\"Link to this section\">¶</a></h2>\
+             <p>Then <math display=\"block\"><mi>y</mi></math></p>"
+        );
+    }
+
+    #[test]
+    fn resolve_autolinks1() {
+        let input = "See https://en.wikipedia.org/wiki/Rust_(language), \
+                     (www.example.com/a?b=1&c=2) or mail me@example.com.
+[https://x.com](https://x.com) isn't linked twice, nor is xhttps://x.com";
+        let note = parse(input, Options::default()).unwrap();
+
+        assert_eq!(
+            note.resolve(input),
+            "<p>See <a href=\"https://en.wikipedia.org/wiki/Rust_(language)\">\
+             https://en.wikipedia.org/wiki/Rust_(language)</a>, \
+             (<a href=\"https://www.example.com/a?b=1&amp;c=2\">www.example.com/a?b=1&amp;c=2</a>) \
+             or mail <a href=\"mailto:me@example.com\">me@example.com</a>.\n\
+             <a href=\"https://x.com\">https://x.com</a> isn't linked twice, nor is xhttps://x.com</p>"
+        );
+
+        let options = Options {
+            autolink: false,
+            ..Options::default()
+        };
+        let note = parse(input, options).unwrap();
+        assert!(!note.resolve(input).contains("mailto"));
+    }
+
+    #[test]
+    fn resolve_ref_links1() {
+        let input = "A [long link][Rust  Book] and [rust book][].
+
+[rust book]: https://doc.rust-lang.org/book/  
+[unused]: https://example.com";
+        let note = parse(input, Options::default()).unwrap();
+
+        assert_eq!(
+            note.resolve(input),
+            "<p>A <a href=\"https://doc.rust-lang.org/book/\">long link</a> and \
+             <a href=\"https://doc.rust-lang.org/book/\">rust book</a>.</p>"
+        );
+        assert_eq!(
+            note.warnings,
+            vec![Warning {
+                message: String::from("Link reference \"unused\" is never used"),
+                pos: 94
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_ref_links1() {
+        let input = "A [link][nowhere] and [text] [x]";
+        //           0123456789
+        let options = Options {
+            strict: true,
+            ..Options::default()
+        };
+
+        assert_eq!(
+            parse(input, options),
+            Err(JustTextError::at(
+                "Link reference \"nowhere\" isn't defined",
+                9
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_ref_links2() {
+        let input = "Set m[i][j] to [zero][].";
+        //           0123456789012345678901
+        let note = parse(input, Options::default()).unwrap();
+
+        assert_eq!(note.resolve(input), "<p>Set m[i][j] to [zero][].</p>");
+        assert_eq!(
+            note.warnings,
+            vec![
+                Warning {
+                    message: String::from("Link reference \"j\" isn't defined"),
+                    pos: 9
+                },
+                Warning {
+                    message: String::from("Link reference \"zero\" isn't defined"),
+                    pos: 16
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_ref_links3() {
+        // Inline code in the title contains what looks like the title's end
+        let input = "[`][x]`]";
+        let note = parse(input, Options::default()).unwrap();
+
+        assert_eq!(
+            note.resolve(input),
+            "<p>[<span class=\"mono\">][x]</span>]</p>"
+        );
+        assert_eq!(
+            note.warnings,
+            vec![Warning {
+                message: String::from("Incomplete link"),
+                pos: 0
+            }]
+        );
+    }
 }
@@ -0,0 +1,132 @@
+use super::common::escape_html;
+use serde_derive::Serialize;
+
+pub struct TocHeading {
+    pub level: usize,
+    pub id: String,
+    pub title: String,
+}
+
+/// A heading in a table of contents, and the headings nested beneath it.
+/// `note.html` renders these for the table of contents at the top of a note.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TocEntry {
+    pub id: String,
+    pub title: String,
+    pub children: Vec<TocEntry>,
+}
+
+/// Nests each heading beneath the nearest preceding heading of a lower level.
+pub fn build_toc(headings: &[TocHeading]) -> Vec<TocEntry> {
+    let mut entries = vec![];
+    let mut i = 0;
+
+    while i < headings.len() {
+        let heading = &headings[i];
+        let end = headings[i + 1..]
+            .iter()
+            .position(|h| h.level <= heading.level)
+            .map(|n| n + i + 1)
+            .unwrap_or(headings.len());
+
+        entries.push(TocEntry {
+            id: heading.id.clone(),
+            title: heading.title.clone(),
+            children: build_toc(&headings[i + 1..end]),
+        });
+        i = end;
+    }
+
+    entries
+}
+
+pub fn render_toc(entries: &[TocEntry]) -> String {
+    format!("<nav class=\"toc\">{}</nav>", render_entries(entries))
+}
+
+fn render_entries(entries: &[TocEntry]) -> String {
+    let mut result = String::from("<ul>");
+    for entry in entries {
+        result.push_str(&format!(
+            "<li><a href=\"#{}\">{}</a>",
+            entry.id,
+            escape_html(&entry.title)
+        ));
+        if !entry.children.is_empty() {
+            result.push_str(&render_entries(&entry.children));
+        }
+        result.push_str("</li>");
+    }
+    result.push_str("</ul>");
+    result
+}
+
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if (c.is_whitespace() || c == '-' || c == '_') && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    let slug = slug.trim_matches('-');
+    if slug.is_empty() {
+        String::from("section")
+    } else {
+        slug.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heading(level: usize, id: &str) -> TocHeading {
+        TocHeading {
+            level,
+            id: id.to_string(),
+            title: id.to_string(),
+        }
+    }
+
+    fn entry(id: &str, children: Vec<TocEntry>) -> TocEntry {
+        TocEntry {
+            id: id.to_string(),
+            title: id.to_string(),
+            children,
+        }
+    }
+
+    #[test]
+    fn build_toc1() {
+        let headings = vec![
+            heading(1, "a"),
+            heading(2, "b"),
+            heading(3, "c"),
+            heading(2, "d"),
+            heading(1, "e"),
+        ];
+
+        assert_eq!(
+            build_toc(&headings),
+            vec![
+                entry(
+                    "a",
+                    vec![entry("b", vec![entry("c", vec![])]), entry("d", vec![])]
+                ),
+                entry("e", vec![])
+            ]
+        );
+    }
+
+    #[test]
+    fn slugify1() {
+        assert_eq!(
+            slugify("  The `foo` function -- again!"),
+            "the-foo-function-again"
+        );
+        assert_eq!(slugify("???"), "section");
+    }
+}
@@ -1,3 +1,4 @@
+use super::config::Config;
 use super::meta::Metadatum;
 use super::note::Note;
 use std::error::Error;
@@ -15,6 +16,18 @@ pub fn read_metadata(meta_path: &Path) -> Result<Vec<Metadatum>, Box<dyn Error>>
     lines.map(|line| line.parse()).collect()
 }
 
+pub fn read_config(config_path: &Path) -> Result<Config, Box<dyn Error>> {
+    if !config_path.exists() {
+        return Ok(Config::default());
+    }
+
+    let mut file = File::open(config_path)?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+
+    Ok(serde_json::from_str(&contents)?)
+}
+
 pub fn read_notes(notes_dir: &Path) -> Result<Vec<Note>, Box<dyn Error>> {
     let paths = collect_note_paths(notes_dir)?;
     let mut notes = vec![];
@@ -28,15 +41,12 @@ pub fn read_notes(notes_dir: &Path) -> Result<Vec<Note>, Box<dyn Error>> {
 
 fn collect_note_paths(notes_dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
     let entries = fs::read_dir(notes_dir)?;
-    let files = entries
-        .filter(|e| e.is_ok())
-        .map(|e| e.unwrap())
-        .filter(|e| {
-            if let Ok(file_type) = e.file_type() {
-                return file_type.is_file();
-            }
-            false
-        });
+    let files = entries.filter_map(|e| e.ok()).filter(|e| {
+        if let Ok(file_type) = e.file_type() {
+            return file_type.is_file();
+        }
+        false
+    });
 
     Ok(files.map(|file| file.path()).collect())
 }
@@ -45,9 +55,7 @@ fn read_note(path: &Path) -> Result<Note, Box<dyn Error>> {
     let mut file = File::open(path)?;
     let mut contents = String::new();
     file.read_to_string(&mut contents)?;
+    let updated = file.metadata()?.modified()?;
 
-    Ok(Note::new(
-        path.to_string_lossy().to_owned().to_string(),
-        contents,
-    ))
+    Note::new(path.to_string_lossy().to_string(), contents, updated.into())
 }
@@ -0,0 +1,104 @@
+use super::config::{Direction, SortConfig, SortKey};
+use super::note::Note;
+use std::cmp::Ordering;
+
+/// Puts notes in the order they should be listed in the index and feeds:
+/// pinned notes first (in the order they were pinned), followed by the rest,
+/// ordered by the configured key and direction. A manual order is always
+/// followed as written, whatever the direction.
+pub fn sort_notes(notes: &mut [Note], config: &SortConfig) {
+    for note in notes.iter_mut() {
+        note.pinned = config.pinned.contains(&note.generate_title());
+    }
+
+    notes.sort_by(|a, b| {
+        let pin_order = |note: &Note| {
+            config
+                .pinned
+                .iter()
+                .position(|title| *title == note.generate_title())
+        };
+
+        match (pin_order(a), pin_order(b)) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => {
+                let ordering = compare(a, b, config);
+                match (config.key, config.direction) {
+                    (SortKey::Manual, _) | (_, Direction::Asc) => ordering,
+                    (_, Direction::Desc) => ordering.reverse(),
+                }
+            }
+        }
+    });
+}
+
+fn compare(a: &Note, b: &Note, config: &SortConfig) -> Ordering {
+    match config.key {
+        SortKey::Created => a.created.cmp(&b.created),
+        SortKey::Updated => a.updated.cmp(&b.updated),
+        SortKey::Title => a.generate_title().cmp(&b.generate_title()),
+        SortKey::Manual => {
+            // Notes missing from the manual order come after those in it
+            let position = |note: &Note| {
+                config
+                    .order
+                    .iter()
+                    .position(|title| *title == note.generate_title())
+                    .unwrap_or(config.order.len())
+            };
+            position(a)
+                .cmp(&position(b))
+                .then(a.created.cmp(&b.created))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::note::test_note;
+
+    fn note(filename: &str, day: u32) -> Note {
+        test_note(filename, "", day)
+    }
+
+    fn titles(notes: &[Note]) -> Vec<String> {
+        notes.iter().map(|note| note.generate_title()).collect()
+    }
+
+    #[test]
+    fn sort_notes1() {
+        let mut notes = vec![
+            note("notes/A.txt", 1),
+            note("notes/B.txt", 2),
+            note("notes/C.txt", 3),
+        ];
+        let config = SortConfig {
+            pinned: vec![String::from("B")],
+            ..SortConfig::default()
+        };
+        sort_notes(&mut notes, &config);
+
+        assert_eq!(titles(&notes), vec!["B", "C", "A"]);
+        assert!(notes[0].pinned && !notes[1].pinned);
+    }
+
+    #[test]
+    fn sort_notes2() {
+        let mut notes = vec![
+            note("notes/A.txt", 1),
+            note("notes/B.txt", 2),
+            note("notes/C.txt", 3),
+        ];
+        let config = SortConfig {
+            key: SortKey::Manual,
+            order: vec![String::from("C"), String::from("A")],
+            ..SortConfig::default()
+        };
+        sort_notes(&mut notes, &config);
+
+        assert_eq!(titles(&notes), vec!["C", "A", "B"]);
+    }
+}
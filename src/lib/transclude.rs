@@ -0,0 +1,225 @@
+use super::error::JustTextError;
+use super::note::Note;
+
+/// How deeply included notes can themselves include notes
+const MAX_DEPTH: usize = 8;
+
+/// A note's includes: each included note's name, index in `notes`, and where
+/// it's included
+type Includes = Vec<(String, usize, usize)>;
+
+/// Renders each note that's included in another, so that it can be embedded
+/// there, with its ids prefixed by "include-1-" for the first note included,
+/// and so on. Including a note that doesn't exist, that (directly or indirectly)
+/// includes the including note, or that nests includes too deeply is an error.
+/// A note counts as updated whenever a note it includes is.
+pub fn transclude(notes: &mut [Note]) -> Result<(), JustTextError<'static>> {
+    let mut includes = vec![];
+    for note in notes.iter() {
+        let mut indices = vec![];
+        for (name, pos) in note.transclusions() {
+            match notes.iter().position(|n| n.is_named(&name)) {
+                Some(i) => indices.push((name, i, pos)),
+                None => {
+                    return Err(JustTextError::new(format!(
+                        "{}: Can't include \"{}\", since there's no such note",
+                        note.locate(pos),
+                        name
+                    )));
+                }
+            }
+        }
+        includes.push(indices);
+    }
+
+    // Notes are rendered after the notes they include
+    let mut order = vec![];
+    let mut depths = vec![None; notes.len()];
+    for i in 0..notes.len() {
+        visit(i, &includes, notes, &mut vec![], &mut depths, &mut order)?;
+    }
+
+    for i in order {
+        // Included notes have already been updated by the notes they include
+        if let Some(updated) = includes[i].iter().map(|(_, j, _)| notes[*j].updated).max() {
+            notes[i].updated = notes[i].updated.max(updated);
+        }
+    }
+
+    for i in 0..notes.len() {
+        let transcluded = render_includes(i, "", &includes, notes);
+        notes[i].set_transcluded(transcluded);
+    }
+
+    Ok(())
+}
+
+/// Renders each note that `i` includes, prefixing its ids with `id_prefix`
+/// and the include's number. A note that's included more than once is
+/// rendered each time, so that its ids are different each time.
+fn render_includes(
+    i: usize,
+    id_prefix: &str,
+    includes: &[Includes],
+    notes: &[Note],
+) -> Vec<String> {
+    includes[i]
+        .iter()
+        .enumerate()
+        .map(|(n, (_, j, _))| {
+            let id_prefix = format!("{}include-{}-", id_prefix, n + 1);
+            let transcluded = render_includes(*j, &id_prefix, includes, notes);
+            notes[*j].render_included(&transcluded, &id_prefix)
+        })
+        .collect()
+}
+
+/// Adds `i` to `order` after the notes it includes, and returns how deeply
+/// includes are nested beneath it. `stack` is the chain of includes that led
+/// to `i`.
+fn visit(
+    i: usize,
+    includes: &[Includes],
+    notes: &[Note],
+    stack: &mut Vec<usize>,
+    depths: &mut [Option<usize>],
+    order: &mut Vec<usize>,
+) -> Result<usize, JustTextError<'static>> {
+    if let Some(depth) = depths[i] {
+        return Ok(depth);
+    }
+
+    stack.push(i);
+    let mut depth = 0;
+    for (name, j, pos) in &includes[i] {
+        if let Some(start) = stack.iter().position(|k| k == j) {
+            let chain = stack[start..]
+                .iter()
+                .chain([j])
+                .map(|k| notes[*k].generate_title())
+                .collect::<Vec<String>>()
+                .join(" -> ");
+            return Err(JustTextError::new(format!(
+                "{}: Can't include \"{}\", since it would include itself ({})",
+                notes[i].locate(*pos),
+                name,
+                chain
+            )));
+        }
+
+        depth = depth.max(visit(*j, includes, notes, stack, depths, order)? + 1);
+        if depth > MAX_DEPTH {
+            return Err(JustTextError::new(format!(
+                "{}: Can't include \"{}\", since includes would be nested more than {} deep",
+                notes[i].locate(*pos),
+                name,
+                MAX_DEPTH
+            )));
+        }
+    }
+    stack.pop();
+
+    depths[i] = Some(depth);
+    order.push(i);
+    Ok(depth)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::note::test_note as note;
+
+    #[test]
+    fn transclude1() {
+        let mut notes = vec![
+            note("notes/A.txt", "Top\n\n![[B]]", 1),
+            note("notes/B.txt", "Middle\n\n![[Shared_Note]]", 2),
+            note("notes/Shared_Note.txt", "Bottom", 3),
+        ];
+        transclude(&mut notes).unwrap();
+
+        assert_eq!(
+            notes[0].render(),
+            "<p>Top</p><div class=\"transclusion\"><p>Middle</p>\
+             <div class=\"transclusion\"><p>Bottom</p></div></div>"
+        );
+        assert_eq!(notes[0].updated, notes[2].updated);
+        assert_eq!(notes[1].updated, notes[2].updated);
+    }
+
+    #[test]
+    fn transclude2() {
+        let mut notes = vec![
+            note("notes/A.txt", "![[B]]", 1),
+            note("notes/B.txt", "Text\n> ![[A]]", 2),
+        ];
+
+        assert_eq!(
+            transclude(&mut notes),
+            Err(JustTextError::new(
+                "notes/B.txt:2:3: Can't include \"A\", since it would include itself (A -> B -> A)"
+            ))
+        );
+
+        let mut notes = vec![note("notes/A.txt", "![[Nowhere]]", 1)];
+        assert_eq!(
+            transclude(&mut notes),
+            Err(JustTextError::new(
+                "notes/A.txt:1:1: Can't include \"Nowhere\", since there's no such note"
+            ))
+        );
+    }
+
+    #[test]
+    fn transclude3() {
+        let mut notes = vec![
+            note("notes/A.txt", "# Setup\n\n![[B]]\n\n![[B]]", 1),
+            note("notes/B.txt", "# Setup\n\n- [ ] Wash[^a]\n\n[^a]: Note.", 2),
+        ];
+        transclude(&mut notes).unwrap();
+
+        let include = |n| {
+            format!(
+                "<div class=\"transclusion\"><h2 id=\"include-{n}-setup\">Setup\
+                 <a class=\"permalink\" href=\"#include-{n}-setup\" aria-label=\"Link to this section\">¶</a></h2>\
+                 <ul><li class=\"task\" id=\"include-{n}-task-1\"><input type=\"checkbox\" disabled> Wash\
+                 <sup class=\"footnote-ref\"><a id=\"include-{n}-fnref-1\" href=\"#include-{n}-fn-1\">1</a></sup></li></ul>\
+                 <section class=\"footnotes\"><ol><li id=\"include-{n}-fn-1\">Note. \
+                 <a class=\"footnote-back\" href=\"#include-{n}-fnref-1\" aria-label=\"Back to reference\">↩</a>\
+                 </li></ol></section></div>",
+                n = n
+            )
+        };
+        assert_eq!(
+            notes[0].render(),
+            format!(
+                "<h2 id=\"setup\">Setup<a class=\"permalink\" href=\"#setup\" \
+                 aria-label=\"Link to this section\">¶</a></h2>{}{}",
+                include(1),
+                include(2)
+            )
+        );
+    }
+
+    #[test]
+    fn transclude4() {
+        let mut notes = vec![
+            note("notes/A.txt", "![[B]]", 1),
+            note(
+                "notes/B.txt",
+                "Set id=\"main\" on [it](#task-1).\n\n![[C]]",
+                2,
+            ),
+            note("notes/C.txt", "- [ ] Wash", 3),
+        ];
+        transclude(&mut notes).unwrap();
+
+        // Text that looks like an id is left as written
+        assert_eq!(
+            notes[0].render(),
+            "<div class=\"transclusion\"><p>Set id=\"main\" on <a href=\"#include-1-task-1\">it</a>.</p>\
+             <div class=\"transclusion\"><ul><li class=\"task\" id=\"include-1-include-1-task-1\">\
+             <input type=\"checkbox\" disabled> Wash</li></ul></div></div>"
+        );
+    }
+}